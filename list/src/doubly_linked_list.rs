@@ -73,11 +73,11 @@ impl BetterTransactionLog {
         })
     }
 
-    pub fn iter(&self) -> ListIterator {
+    pub fn iter(&self) -> ListIterator<'_> {
         ListIterator::new(self.head.as_ref().map(|head| head.borrow()))
     }
 
-    pub fn back_iter(&self) -> ListIterator {
+    pub fn back_iter(&self) -> ListIterator<'_> {
         ListIterator::new(self.tail.as_ref().map(|tail| tail.borrow()))
     }
 }
@@ -105,7 +105,7 @@ impl<'a> Iterator for ListIterator<'a> {
             Some(current) => unsafe {
                 self.current = match (*current.as_ptr()).next {
                     None => None,
-                    Some(ref next) => Some(Rc::borrow(&next)),
+                    Some(ref next) => Some(Rc::borrow(next)),
                 };
                 Some(&(*current.as_ptr()).value)
             }
@@ -117,7 +117,7 @@ impl<'a> DoubleEndedIterator for ListIterator<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.current.take().map(|current| unsafe {
             let current = &*current.as_ptr();
-            self.current = current.prev.as_ref().map(|prev| Rc::borrow(&prev));
+            self.current = current.prev.as_ref().map(Rc::borrow);
             &current.value
         })
     }
@@ -1,12 +1,22 @@
 use std::cmp;
+use std::ops::Range;
+
+use crate::timestamp_stats::TimestampStats;
 
 type Node = Option<u64>;
 
 const MIN_SIZE: usize = 10;
+const STATS_BLOCK_SIZE: usize = 64;
 
+// 间隔取相邻两次 append 的差的绝对值，时间戳可以乱序追加
 pub struct TimestampSaver {
     buf: Box<[Node]>,
-    pub length: usize,
+    length: usize,
+    // 所有时间戳是否按非递减顺序追加
+    sorted: bool,
+    stats: TimestampStats,
+    // 每 `STATS_BLOCK_SIZE` 个时间戳的统计，不含与上一块之间的间隔
+    block_stats: Vec<TimestampStats>,
 }
 
 impl TimestampSaver {
@@ -14,9 +24,20 @@ impl TimestampSaver {
         TimestampSaver {
             buf: vec![None; MIN_SIZE].into_boxed_slice(),
             length: 0,
+            sorted: true,
+            stats: TimestampStats::default(),
+            block_stats: vec![],
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
     pub fn cap(&self) -> usize {
         self.buf.len()
     }
@@ -35,6 +56,20 @@ impl TimestampSaver {
         if self.length == self.cap() {
             self.grow(self.length + 1);
         }
+        if let Some(prev) = self.length.checked_sub(1).and_then(|prev| self.buf[prev]) {
+            self.sorted &= value >= prev;
+            let gap = value.abs_diff(prev);
+            self.stats.add_gap(gap);
+            if !self.length.is_multiple_of(STATS_BLOCK_SIZE) {
+                self.block_stats.last_mut().unwrap().add_gap(gap);
+            }
+        }
+        if self.length.is_multiple_of(STATS_BLOCK_SIZE) {
+            self.block_stats.push(TimestampStats::default());
+        }
+        self.block_stats.last_mut().unwrap().add_timestamp(value);
+        self.stats.add_timestamp(value);
+
         self.buf[self.length] = Some(value);
         self.length += 1;
     }
@@ -46,6 +81,48 @@ impl TimestampSaver {
             None
         }
     }
+
+    pub fn stats(&self) -> &TimestampStats {
+        &self.stats
+    }
+
+    // 按顺序追加时二分定位并合并整块的统计，否则逐个扫描，间隔取范围内相邻时间戳之差
+    pub fn range_stats(&self, range: Range<u64>) -> TimestampStats {
+        let timestamps = &self.buf[..self.length];
+        if !self.sorted {
+            let mut stats = TimestampStats::default();
+            let mut prev = None;
+            for timestamp in timestamps.iter().map(|t| t.unwrap()) {
+                if range.contains(&timestamp) {
+                    if let Some(prev) = prev {
+                        stats.add_gap(timestamp.abs_diff(prev));
+                    }
+                    stats.add_timestamp(timestamp);
+                    prev = Some(timestamp);
+                }
+            }
+            return stats;
+        }
+        let start = timestamps.partition_point(|&t| t < Some(range.start));
+        let end = timestamps.partition_point(|&t| t < Some(range.end));
+
+        let mut stats = TimestampStats::default();
+        let mut index = start;
+        while index < end {
+            let timestamp = timestamps[index].unwrap();
+            if index > start {
+                stats.add_gap(timestamp - timestamps[index - 1].unwrap());
+            }
+            if index.is_multiple_of(STATS_BLOCK_SIZE) && index + STATS_BLOCK_SIZE <= end {
+                stats.merge(&self.block_stats[index / STATS_BLOCK_SIZE]);
+                index += STATS_BLOCK_SIZE;
+            } else {
+                stats.add_timestamp(timestamp);
+                index += 1;
+            }
+        }
+        stats
+    }
 }
 
 impl IntoIterator for TimestampSaver {
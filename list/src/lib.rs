@@ -1,4 +1,5 @@
 pub mod singly_linked_list;
 pub mod doubly_linked_list;
 pub mod skip_list;
-pub mod dynamic_array;
\ No newline at end of file
+pub mod dynamic_array;
+pub mod timestamp_stats;
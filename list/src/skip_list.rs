@@ -169,7 +169,7 @@ impl std::fmt::Debug for BestTransactionLog {
                     for log in self.iter(level) {
                         write!(f, "[{}] ", log.0)?;
                     }
-                    writeln!(f, "")?;
+                    writeln!(f)?;
                 }
             }
         };
@@ -0,0 +1,140 @@
+use std::cmp;
+use std::collections::BTreeMap;
+
+const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+
+// 按对数分桶的分位数草图，每个估计值与某个真实值的相对误差不超过 `relative_accuracy`
+#[derive(Clone)]
+pub struct QuantileSketch {
+    gamma: f64,
+    log_gamma: f64,
+    zero_count: u64,
+    buckets: BTreeMap<i32, u64>,
+    count: u64,
+}
+
+impl Default for QuantileSketch {
+    fn default() -> Self {
+        Self::new(DEFAULT_RELATIVE_ACCURACY)
+    }
+}
+
+impl QuantileSketch {
+    pub fn new(relative_accuracy: f64) -> Self {
+        assert!(relative_accuracy > 0.0 && relative_accuracy < 1.0);
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            gamma,
+            log_gamma: gamma.ln(),
+            zero_count: 0,
+            buckets: BTreeMap::new(),
+            count: 0,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn insert(&mut self, value: u64) {
+        self.count += 1;
+        if value == 0 {
+            self.zero_count += 1;
+        } else {
+            let index = ((value as f64).ln() / self.log_gamma).ceil() as i32;
+            *self.buckets.entry(index).or_insert(0) += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        assert_eq!(self.gamma.to_bits(), other.gamma.to_bits());
+        self.count += other.count;
+        self.zero_count += other.zero_count;
+        for (&index, &count) in other.buckets.iter() {
+            *self.buckets.entry(index).or_insert(0) += count;
+        }
+    }
+
+    // `quantile` 取值 `[0, 1]`，例如 `0.99` 表示 99 分位
+    pub fn quantile(&self, quantile: f64) -> Option<u64> {
+        assert!((0.0..=1.0).contains(&quantile));
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = (quantile * (self.count - 1) as f64) as u64;
+        if rank < self.zero_count {
+            return Some(0);
+        }
+        let mut seen = self.zero_count;
+        for (&index, &count) in self.buckets.iter() {
+            seen += count;
+            if rank < seen {
+                let estimate = 2.0 * self.gamma.powi(index) / (self.gamma + 1.0);
+                return Some(estimate.round() as u64);
+            }
+        }
+        unreachable!()
+    }
+}
+
+// 一段时间戳的统计，间隔取相邻时间戳之差
+#[derive(Clone, Default)]
+pub struct TimestampStats {
+    count: usize,
+    min: Option<u64>,
+    max: Option<u64>,
+    gap_sum: u128,
+    gaps: QuantileSketch,
+}
+
+impl TimestampStats {
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        self.max
+    }
+
+    pub fn mean_gap(&self) -> Option<f64> {
+        match self.gaps.count() {
+            0 => None,
+            gap_count => Some(self.gap_sum as f64 / gap_count as f64),
+        }
+    }
+
+    pub fn gap_percentile(&self, quantile: f64) -> Option<u64> {
+        self.gaps.quantile(quantile)
+    }
+
+    pub(crate) fn add_timestamp(&mut self, timestamp: u64) {
+        self.count += 1;
+        self.min = Some(self.min.map_or(timestamp, |min| cmp::min(min, timestamp)));
+        self.max = Some(self.max.map_or(timestamp, |max| cmp::max(max, timestamp)));
+    }
+
+    pub(crate) fn add_gap(&mut self, gap: u64) {
+        self.gap_sum += gap as u128;
+        self.gaps.insert(gap);
+    }
+
+    // 合并时不计入 `self` 最后一个与 `other` 第一个时间戳之间的间隔
+    pub(crate) fn merge(&mut self, other: &TimestampStats) {
+        self.count += other.count;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(cmp::min(a, b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(cmp::max(a, b)),
+            (a, b) => a.or(b),
+        };
+        self.gap_sum += other.gap_sum;
+        self.gaps.merge(&other.gaps);
+    }
+}
@@ -0,0 +1,109 @@
+use rand::Rng;
+
+use list::dynamic_array::TimestampSaver;
+
+fn percentile(sorted: &[u64], quantile: f64) -> u64 {
+    sorted[(quantile * (sorted.len() - 1) as f64) as usize]
+}
+
+fn assert_close(estimate: u64, exact: u64) {
+    let error = (estimate as f64 - exact as f64).abs();
+    assert!(
+        error <= exact as f64 * 0.011 + 1.0,
+        "{} vs {}",
+        estimate,
+        exact
+    );
+}
+
+#[test]
+fn timestamp_stats() {
+    let mut rng = rand::thread_rng();
+    let mut saver = TimestampSaver::new_empty();
+    assert_eq!(saver.stats().count(), 0);
+    assert_eq!(saver.stats().min(), None);
+    assert_eq!(saver.stats().mean_gap(), None);
+    assert_eq!(saver.stats().gap_percentile(0.5), None);
+
+    let mut timestamps = vec![];
+    let mut timestamp = 1_000u64;
+    for _ in 0..1000 {
+        timestamp += rng.gen_range(0..10_000);
+        timestamps.push(timestamp);
+        saver.append(timestamp);
+    }
+
+    let mut gaps: Vec<u64> = timestamps.windows(2).map(|w| w[1] - w[0]).collect();
+    gaps.sort_unstable();
+    let stats = saver.stats();
+    assert_eq!(stats.count(), 1000);
+    assert_eq!(stats.min(), timestamps.first().copied());
+    assert_eq!(stats.max(), timestamps.last().copied());
+    let mean = gaps.iter().sum::<u64>() as f64 / gaps.len() as f64;
+    assert!((stats.mean_gap().unwrap() - mean).abs() < 1e-6);
+    for &quantile in &[0.0, 0.5, 0.9, 0.99, 1.0] {
+        assert_close(
+            stats.gap_percentile(quantile).unwrap(),
+            percentile(&gaps, quantile),
+        );
+    }
+}
+
+#[test]
+fn timestamp_range_stats() {
+    let mut rng = rand::thread_rng();
+    let mut saver = TimestampSaver::new_empty();
+    let mut timestamps = vec![];
+    let mut timestamp = 0u64;
+    for _ in 0..500 {
+        timestamp += rng.gen_range(0..100);
+        timestamps.push(timestamp);
+        saver.append(timestamp);
+    }
+
+    for _ in 0..200 {
+        let start = rng.gen_range(0..timestamp + 10);
+        let end = rng.gen_range(start..timestamp + 20);
+        let in_range: Vec<u64> = timestamps
+            .iter()
+            .copied()
+            .filter(|t| (start..end).contains(t))
+            .collect();
+        let stats = saver.range_stats(start..end);
+        assert_eq!(stats.count(), in_range.len());
+        assert_eq!(stats.min(), in_range.first().copied());
+        assert_eq!(stats.max(), in_range.last().copied());
+
+        let mut gaps: Vec<u64> = in_range.windows(2).map(|w| w[1] - w[0]).collect();
+        gaps.sort_unstable();
+        if gaps.is_empty() {
+            assert_eq!(stats.mean_gap(), None);
+        } else {
+            let mean = gaps.iter().sum::<u64>() as f64 / gaps.len() as f64;
+            assert!((stats.mean_gap().unwrap() - mean).abs() < 1e-6);
+            assert_close(stats.gap_percentile(0.5).unwrap(), percentile(&gaps, 0.5));
+            assert_close(stats.gap_percentile(0.99).unwrap(), percentile(&gaps, 0.99));
+        }
+    }
+}
+
+#[test]
+fn timestamp_out_of_order() {
+    let mut saver = TimestampSaver::new_empty();
+    for &timestamp in &[10, 5, 20, 30, 25] {
+        saver.append(timestamp);
+    }
+    assert_eq!(saver.len(), 5);
+
+    let stats = saver.stats();
+    assert_eq!(stats.count(), 5);
+    assert_eq!(stats.min(), Some(5));
+    assert_eq!(stats.max(), Some(30));
+    assert!((stats.mean_gap().unwrap() - 8.75).abs() < 1e-6);
+
+    let stats = saver.range_stats(10..30);
+    assert_eq!(stats.count(), 3);
+    assert_eq!(stats.min(), Some(10));
+    assert_eq!(stats.max(), Some(25));
+    assert!((stats.mean_gap().unwrap() - 7.5).abs() < 1e-6);
+}
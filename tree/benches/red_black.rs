@@ -0,0 +1,127 @@
+#![feature(test)]
+extern crate test;
+
+use std::collections::BTreeMap;
+
+use rand::prelude::SliceRandom;
+use test::Bencher;
+
+use tree::red_black::RedBlackTree;
+
+mod red_black_rc;
+
+const NUM: u64 = 10_000;
+
+fn shuffled() -> Vec<u64> {
+    let mut nums: Vec<u64> = (0..NUM).collect();
+    nums.shuffle(&mut rand::thread_rng());
+    nums
+}
+
+#[bench]
+fn add_arena(b: &mut Bencher) {
+    let nums = shuffled();
+    b.iter(|| {
+        let mut tree = RedBlackTree::default();
+        nums.iter().for_each(|&i| {
+            tree.add(i, i);
+        });
+        tree
+    });
+}
+
+#[bench]
+fn add_rc(b: &mut Bencher) {
+    let nums = shuffled();
+    b.iter(|| {
+        let mut tree = red_black_rc::RedBlackTree::default();
+        nums.iter().for_each(|&i| {
+            tree.add(i, i);
+        });
+        tree
+    });
+}
+
+#[bench]
+fn add_std(b: &mut Bencher) {
+    let nums = shuffled();
+    b.iter(|| {
+        let mut tree = BTreeMap::default();
+        nums.iter().for_each(|&i| {
+            tree.insert(i, i);
+        });
+        tree
+    });
+}
+
+#[bench]
+fn remove_arena(b: &mut Bencher) {
+    let nums = shuffled();
+    let to_remove = shuffled();
+    b.iter(|| {
+        let mut tree = RedBlackTree::default();
+        nums.iter().for_each(|&i| {
+            tree.add(i, i);
+        });
+        to_remove.iter().for_each(|i| {
+            tree.remove(i);
+        });
+    });
+}
+
+#[bench]
+fn remove_rc(b: &mut Bencher) {
+    let nums = shuffled();
+    let to_remove = shuffled();
+    b.iter(|| {
+        let mut tree = red_black_rc::RedBlackTree::default();
+        nums.iter().for_each(|&i| {
+            tree.add(i, i);
+        });
+        to_remove.iter().for_each(|i| {
+            tree.remove(i);
+        });
+    });
+}
+
+#[bench]
+fn remove_std(b: &mut Bencher) {
+    let nums = shuffled();
+    let to_remove = shuffled();
+    b.iter(|| {
+        let mut tree = BTreeMap::default();
+        nums.iter().for_each(|&i| {
+            tree.insert(i, i);
+        });
+        to_remove.iter().for_each(|i| {
+            tree.remove(i);
+        });
+    });
+}
+
+#[bench]
+fn iter_arena(b: &mut Bencher) {
+    let mut tree = RedBlackTree::default();
+    shuffled().iter().for_each(|&i| {
+        tree.add(i, i);
+    });
+    b.iter(|| tree.iter().map(|pair| *pair.value()).sum::<u64>());
+}
+
+#[bench]
+fn iter_rc(b: &mut Bencher) {
+    let mut tree = red_black_rc::RedBlackTree::default();
+    shuffled().iter().for_each(|&i| {
+        tree.add(i, i);
+    });
+    b.iter(|| tree.iter().map(|pair| *pair.value()).sum::<u64>());
+}
+
+#[bench]
+fn iter_std(b: &mut Bencher) {
+    let mut tree = BTreeMap::default();
+    shuffled().iter().for_each(|&i| {
+        tree.insert(i, i);
+    });
+    b.iter(|| tree.values().sum::<u64>());
+}
@@ -0,0 +1,313 @@
+use std::cmp;
+use std::cmp::Ordering;
+
+type Tree<K, V> = Option<Box<Node<K, V>>>;
+
+struct Node<K: Ord, V> {
+    key: K,
+    value: V,
+    height: usize,
+    left: Tree<K, V>,
+    right: Tree<K, V>,
+}
+
+impl<K: Ord, V> Node<K, V> {
+    fn new(key: K, value: V) -> Box<Self> {
+        Box::new(Self {
+            key,
+            value,
+            height: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn height(tree: &Tree<K, V>) -> usize {
+        tree.as_ref().map_or(0, |node| node.height)
+    }
+
+    fn update_height(&mut self) {
+        self.height = cmp::max(Self::height(&self.left), Self::height(&self.right)) + 1;
+    }
+
+    fn balance_factor(&self) -> isize {
+        Self::height(&self.left) as isize - Self::height(&self.right) as isize
+    }
+
+    fn rotate_right(mut self: Box<Self>) -> Box<Self> {
+        let mut left = self.left.take().unwrap();
+        self.left = left.right.take();
+        self.update_height();
+        left.right = Some(self);
+        left.update_height();
+        left
+    }
+
+    fn rotate_left(mut self: Box<Self>) -> Box<Self> {
+        let mut right = self.right.take().unwrap();
+        self.right = right.left.take();
+        self.update_height();
+        right.left = Some(self);
+        right.update_height();
+        right
+    }
+
+    fn rebalance(mut self: Box<Self>) -> Box<Self> {
+        self.update_height();
+        match self.balance_factor() {
+            2 => {
+                if self.left.as_ref().unwrap().balance_factor() < 0 {
+                    self.left = Some(self.left.take().unwrap().rotate_left());
+                }
+                self.rotate_right()
+            }
+            -2 => {
+                if self.right.as_ref().unwrap().balance_factor() > 0 {
+                    self.right = Some(self.right.take().unwrap().rotate_right());
+                }
+                self.rotate_left()
+            }
+            _ => self,
+        }
+    }
+}
+
+pub struct AvlTree<K: Ord, V> {
+    root: Tree<K, V>,
+    length: usize,
+}
+
+impl<K: Ord, V> Default for AvlTree<K, V> {
+    fn default() -> Self {
+        Self {
+            root: None,
+            length: 0,
+        }
+    }
+}
+
+impl<K: Ord, V> AvlTree<K, V> {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        match Self::validate(&self.root, None, None) {
+            Ok((_height, count)) => count == self.length,
+            Err(()) => false,
+        }
+    }
+    // 返回子树的高度和节点数
+    fn validate(tree: &Tree<K, V>, min: Option<&K>, max: Option<&K>) -> Result<(usize, usize), ()> {
+        match tree {
+            None => Ok((0, 0)),
+            Some(node) => {
+                if min.is_some_and(|min| node.key <= *min)
+                    || max.is_some_and(|max| node.key >= *max)
+                {
+                    return Err(());
+                }
+                let (left_height, left_count) = Self::validate(&node.left, min, Some(&node.key))?;
+                let (right_height, right_count) =
+                    Self::validate(&node.right, Some(&node.key), max)?;
+                let height = cmp::max(left_height, right_height) + 1;
+                if node.height != height || left_height.abs_diff(right_height) > 1 {
+                    return Err(());
+                }
+                Ok((height, left_count + right_count + 1))
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (root, old_value) = Self::insert_rec(self.root.take(), key, value);
+        self.root = Some(root);
+        if old_value.is_none() {
+            self.length += 1;
+        }
+        old_value
+    }
+    fn insert_rec(tree: Tree<K, V>, key: K, value: V) -> (Box<Node<K, V>>, Option<V>) {
+        match tree {
+            None => (Node::new(key, value), None),
+            Some(mut node) => {
+                let old_value = match key.cmp(&node.key) {
+                    Ordering::Less => {
+                        let (left, old_value) = Self::insert_rec(node.left.take(), key, value);
+                        node.left = Some(left);
+                        old_value
+                    }
+                    Ordering::Equal => {
+                        let old_value = std::mem::replace(&mut node.value, value);
+                        return (node, Some(old_value));
+                    }
+                    Ordering::Greater => {
+                        let (right, old_value) = Self::insert_rec(node.right.take(), key, value);
+                        node.right = Some(right);
+                        old_value
+                    }
+                };
+                (node.rebalance(), old_value)
+            }
+        }
+    }
+
+    pub fn find(&self, target: &K) -> Option<&V> {
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            tree = match target.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Greater => &node.right,
+            };
+        }
+        None
+    }
+
+    pub fn contains_key(&self, target: &K) -> bool {
+        self.find(target).is_some()
+    }
+
+    pub fn remove(&mut self, target: &K) -> Option<V> {
+        let removed = Self::remove_rec(&mut self.root, target);
+        if removed.is_some() {
+            self.length -= 1;
+        }
+        removed
+    }
+    fn remove_rec(tree: &mut Tree<K, V>, target: &K) -> Option<V> {
+        let mut node = tree.take()?;
+        let removed = match target.cmp(&node.key) {
+            Ordering::Less => Self::remove_rec(&mut node.left, target),
+            Ordering::Equal => {
+                *tree = match (node.left.take(), node.right.take()) {
+                    (None, None) => None,
+                    (Some(child), None) | (None, Some(child)) => Some(child),
+                    (Some(left), Some(right)) => {
+                        // 用中序后继替换被删除的节点
+                        let (mut successor, right) = Self::take_min(right);
+                        successor.left = Some(left);
+                        successor.right = right;
+                        Some(successor.rebalance())
+                    }
+                };
+                return Some(node.value);
+            }
+            Ordering::Greater => Self::remove_rec(&mut node.right, target),
+        };
+        *tree = Some(node.rebalance());
+        removed
+    }
+    fn take_min(mut node: Box<Node<K, V>>) -> (Box<Node<K, V>>, Tree<K, V>) {
+        match node.left.take() {
+            None => {
+                let right = node.right.take();
+                (node, right)
+            }
+            Some(left) => {
+                let (min, left) = Self::take_min(left);
+                node.left = left;
+                (min, Some(node.rebalance()))
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter {
+            stack: vec![],
+            remain_len: self.length,
+        };
+        iter.push_left(&self.root);
+        iter
+    }
+}
+
+pub struct Iter<'t, K: Ord, V> {
+    stack: Vec<&'t Node<K, V>>,
+    remain_len: usize,
+}
+
+impl<'t, K: Ord, V> Iter<'t, K, V> {
+    fn push_left(&mut self, mut tree: &'t Tree<K, V>) {
+        while let Some(node) = tree {
+            self.stack.push(node);
+            tree = &node.left;
+        }
+    }
+}
+
+impl<'t, K: Ord, V> Iterator for Iter<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.remain_len -= 1;
+        self.push_left(&node.right);
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::SliceRandom;
+    use rand::Rng;
+
+    use crate::avl::*;
+
+    #[test]
+    fn insert_and_remove() {
+        const NUM: i32 = 1000;
+        let mut tree = AvlTree::default();
+        let mut elements: Vec<i32> = (0..NUM).collect();
+        let mut rng = rand::thread_rng();
+        elements.shuffle(&mut rng);
+        let mut len = 0;
+        elements.iter().for_each(|&element| {
+            assert_eq!(tree.insert(element, element), None);
+            assert!(tree.is_valid());
+            len += 1;
+            assert_eq!(tree.len(), len);
+        });
+        assert_eq!(tree.insert(99, 99), Some(99));
+        assert_eq!(tree.len(), NUM as usize);
+        assert!(tree
+            .iter()
+            .map(|(&k, &v)| (k, v))
+            .eq((0..NUM).map(|i| (i, i))));
+
+        while !tree.is_empty() {
+            let to_remove = rng.gen_range(0..NUM);
+            match tree.remove(&to_remove) {
+                None => assert!(!tree.contains_key(&to_remove)),
+                Some(removed) => {
+                    assert_eq!(removed, to_remove);
+                    len -= 1;
+                }
+            }
+            assert!(tree.is_valid());
+            assert_eq!(tree.len(), len);
+        }
+    }
+
+    #[test]
+    fn sorted_insert() {
+        const NUM: u32 = 1 << 16;
+        let mut tree = AvlTree::default();
+        for i in 0..NUM {
+            tree.insert(i, i);
+        }
+        assert!(tree.is_valid());
+        // AVL 树的高度不超过 1.44 * log2(n + 2)
+        assert!(tree.root.as_ref().unwrap().height <= 24);
+        assert_eq!(tree.find(&(NUM - 1)), Some(&(NUM - 1)));
+        assert_eq!(tree.find(&NUM), None);
+    }
+}
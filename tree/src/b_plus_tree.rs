@@ -0,0 +1,693 @@
+use std::ops::{Bound, RangeBounds};
+
+use crate::invariant::InvariantViolation;
+
+type Index = u32;
+
+// 空子节点、根节点的父节点或首尾叶子节点的兄弟
+const NIL: Index = Index::MAX;
+
+struct Node<K, V> {
+    // 内部节点中为分隔键，右侧子树中的键都不小于它，左侧子树中的键都小于它
+    keys: Vec<K>,
+    // 只有叶子节点有值
+    values: Vec<V>,
+    // 只有内部节点有子节点
+    children: Vec<Index>,
+    // 叶子节点的左右兄弟
+    prev: Index,
+    next: Index,
+}
+
+impl<K, V> Node<K, V> {
+    fn new_leaf() -> Self {
+        Self {
+            keys: vec![],
+            values: vec![],
+            children: vec![],
+            prev: NIL,
+            next: NIL,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    // 叶子节点按键值对个数加 1 计算
+    fn children_len(&self) -> usize {
+        if self.is_leaf() {
+            self.keys.len() + 1
+        } else {
+            self.children.len()
+        }
+    }
+
+    fn child_index(&self, key: &K) -> usize
+    where
+        K: Ord,
+    {
+        match self.keys.binary_search(key) {
+            Ok(found) => found + 1,
+            Err(child_index) => child_index,
+        }
+    }
+}
+
+// 校验时按中序访问叶子节点的状态
+struct LeafWalk {
+    depth: Option<usize>,
+    last: Index,
+    last_path: Vec<usize>,
+}
+
+pub struct BPlusTree<K: Ord, V> {
+    nodes: Vec<Node<K, V>>,
+    free: Vec<Index>,
+    root: Index,
+    max_children_length: usize,
+    length: usize,
+}
+
+impl<K: Ord, V> Default for BPlusTree<K, V> {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl<K: Ord, V> BPlusTree<K, V> {
+    pub fn new(max_children_length: usize) -> Self {
+        assert!(max_children_length >= 4);
+        Self {
+            nodes: vec![],
+            free: vec![],
+            root: NIL,
+            max_children_length,
+            length: 0,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.length
+    }
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let mut walk = LeafWalk {
+            depth: None,
+            last: NIL,
+            last_path: vec![],
+        };
+        let count = if self.root == NIL {
+            0
+        } else {
+            self.validate_node(self.root, None, None, &mut vec![], &mut walk)?
+        };
+        if walk.last != NIL && self.node(walk.last).next != NIL {
+            return Err(InvariantViolation::LeafLink {
+                path: walk.last_path,
+            });
+        }
+        if count != self.length {
+            return Err(InvariantViolation::Length {
+                recorded: self.length,
+                counted: count,
+            });
+        }
+        Ok(())
+    }
+    // 返回子树中键值对的个数，子树中的键在 [min, max) 内
+    fn validate_node(
+        &self,
+        index: Index,
+        min: Option<&K>,
+        max: Option<&K>,
+        path: &mut Vec<usize>,
+        walk: &mut LeafWalk,
+    ) -> Result<usize, InvariantViolation> {
+        let node = self.node(index);
+        if !node.is_leaf() && node.children.len() != node.keys.len() + 1 {
+            return Err(InvariantViolation::ChildCount {
+                path: path.clone(),
+                pairs: node.keys.len(),
+                children: node.children.len(),
+            });
+        }
+        let children = node.children_len();
+        let min_children = if path.is_empty() {
+            2
+        } else {
+            self.max_children_length / 2
+        };
+        if children < min_children || children > self.max_children_length {
+            return Err(InvariantViolation::FanOut {
+                path: path.clone(),
+                children,
+                min: min_children,
+                max: self.max_children_length,
+            });
+        }
+
+        // 叶子节点中的键可以等于下界，分隔键则一定大于下界
+        let mut prev = min;
+        for (i, key) in node.keys.iter().enumerate() {
+            let out_of_order = match prev {
+                None => false,
+                Some(prev) if i == 0 && node.is_leaf() => key < prev,
+                Some(prev) => key <= prev,
+            };
+            if out_of_order {
+                return Err(InvariantViolation::KeyOrder { path: path.clone() });
+            }
+            prev = Some(key);
+        }
+        if max.is_some_and(|max| node.keys.last().is_some_and(|last| last >= max)) {
+            return Err(InvariantViolation::KeyOrder { path: path.clone() });
+        }
+
+        if node.is_leaf() {
+            match walk.depth {
+                None => walk.depth = Some(path.len()),
+                Some(expected) if expected != path.len() => {
+                    return Err(InvariantViolation::LeafDepth {
+                        path: path.clone(),
+                        depth: path.len(),
+                        expected,
+                    })
+                }
+                Some(_) => {}
+            }
+            if node.prev != walk.last || (walk.last != NIL && self.node(walk.last).next != index) {
+                return Err(InvariantViolation::LeafLink { path: path.clone() });
+            }
+            walk.last = index;
+            walk.last_path = path.clone();
+            return Ok(node.keys.len());
+        }
+        let mut count = 0;
+        for (i, &child) in node.children.iter().enumerate() {
+            let child_min = i.checked_sub(1).map_or(min, |i| Some(&node.keys[i]));
+            let child_max = node.keys.get(i).map_or(max, Some);
+            path.push(i);
+            count += self.validate_node(child, child_min, child_max, path, walk)?;
+            path.pop();
+        }
+        Ok(count)
+    }
+
+    fn node(&self, index: Index) -> &Node<K, V> {
+        &self.nodes[index as usize]
+    }
+    fn node_mut(&mut self, index: Index) -> &mut Node<K, V> {
+        &mut self.nodes[index as usize]
+    }
+    fn alloc(&mut self, node: Node<K, V>) -> Index {
+        match self.free.pop() {
+            Some(index) => {
+                *self.node_mut(index) = node;
+                index
+            }
+            None => {
+                assert!(self.nodes.len() < NIL as usize);
+                self.nodes.push(node);
+                (self.nodes.len() - 1) as Index
+            }
+        }
+    }
+    fn dealloc(&mut self, index: Index) {
+        *self.node_mut(index) = Node::new_leaf();
+        self.free.push(index);
+    }
+
+    fn leaf_of(&self, key: &K) -> Index {
+        let mut index = self.root;
+        while !self.node(index).is_leaf() {
+            let node = self.node(index);
+            index = node.children[node.child_index(key)];
+        }
+        index
+    }
+    pub fn get(&self, key: &K) -> Option<&V> {
+        if self.root == NIL {
+            return None;
+        }
+        let leaf = self.node(self.leaf_of(key));
+        let found = leaf.keys.binary_search(key).ok()?;
+        Some(&leaf.values[found])
+    }
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if self.root == NIL {
+            return None;
+        }
+        let leaf = self.leaf_of(key);
+        let leaf = self.node_mut(leaf);
+        let found = leaf.keys.binary_search(key).ok()?;
+        Some(&mut leaf.values[found])
+    }
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range(..)
+    }
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let is_empty = match (range.start_bound(), range.end_bound()) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) => start >= end,
+            _ => false,
+        };
+        if self.root == NIL || is_empty {
+            return Range {
+                tree: self,
+                front: (NIL, 0),
+                back: (NIL, 0),
+            };
+        }
+
+        let front = match range.start_bound() {
+            Bound::Included(key) => self.seek(key, true),
+            Bound::Excluded(key) => self.seek(key, false),
+            Bound::Unbounded => {
+                let mut index = self.root;
+                while !self.node(index).is_leaf() {
+                    index = self.node(index).children[0];
+                }
+                (index, 0)
+            }
+        };
+        let back = match range.end_bound() {
+            Bound::Included(key) => self.seek(key, false),
+            Bound::Excluded(key) => self.seek(key, true),
+            Bound::Unbounded => {
+                let mut index = self.root;
+                while !self.node(index).is_leaf() {
+                    index = *self.node(index).children.last().unwrap();
+                }
+                (index, self.node(index).keys.len())
+            }
+        };
+        Range {
+            tree: self,
+            front: self.canonical(front),
+            back: self.canonical(back),
+        }
+    }
+    // 第一个不小于 key（inclusive 为 false 时为大于 key）的键值对的位置
+    fn seek(&self, key: &K, inclusive: bool) -> (Index, usize) {
+        let leaf = self.leaf_of(key);
+        let keys = &self.node(leaf).keys;
+        let index = if inclusive {
+            keys.partition_point(|k| k < key)
+        } else {
+            keys.partition_point(|k| k <= key)
+        };
+        (leaf, index)
+    }
+    // 叶子节点的末尾和下一个叶子节点的开头是同一个位置，统一为后者
+    fn canonical(&self, (leaf, index): (Index, usize)) -> (Index, usize) {
+        let node = self.node(leaf);
+        if index == node.keys.len() && node.next != NIL {
+            (node.next, 0)
+        } else {
+            (leaf, index)
+        }
+    }
+}
+impl<K: Ord + Clone, V> BPlusTree<K, V> {
+    pub fn add(&mut self, key: K, value: V) -> Option<V> {
+        if self.root == NIL {
+            self.root = self.alloc(Node::new_leaf());
+        }
+        let root = self.root;
+        let (split, old_value) = self.add_r(root, key, value);
+        if let Some((separator, right)) = split {
+            self.root = self.alloc(Node {
+                keys: vec![separator],
+                values: vec![],
+                children: vec![root, right],
+                prev: NIL,
+                next: NIL,
+            });
+        }
+        old_value
+    }
+    // 节点分裂时返回分隔键和新的右侧节点
+    fn add_r(&mut self, index: Index, key: K, value: V) -> (Option<(K, Index)>, Option<V>) {
+        let node = self.node_mut(index);
+        if node.is_leaf() {
+            match node.keys.binary_search(&key) {
+                Ok(found) => {
+                    let old_value = std::mem::replace(&mut node.values[found], value);
+                    return (None, Some(old_value));
+                }
+                Err(to_insert) => {
+                    node.keys.insert(to_insert, key);
+                    node.values.insert(to_insert, value);
+                }
+            }
+            self.length += 1;
+            if self.node(index).keys.len() < self.max_children_length {
+                return (None, None);
+            }
+            return (Some(self.split_leaf(index)), None);
+        }
+
+        let child_index = node.child_index(&key);
+        let child = node.children[child_index];
+        let (split, old_value) = self.add_r(child, key, value);
+        if let Some((separator, right)) = split {
+            let node = self.node_mut(index);
+            node.keys.insert(child_index, separator);
+            node.children.insert(child_index + 1, right);
+            if node.children.len() > self.max_children_length {
+                return (Some(self.split_internal(index)), old_value);
+            }
+        }
+        (None, old_value)
+    }
+    fn split_leaf(&mut self, index: Index) -> (K, Index) {
+        let node = self.node_mut(index);
+        let mid = node.keys.len() / 2;
+        let keys = node.keys.split_off(mid);
+        let values = node.values.split_off(mid);
+        let next = node.next;
+        let separator = keys[0].clone();
+        let right = self.alloc(Node {
+            keys,
+            values,
+            children: vec![],
+            prev: index,
+            next,
+        });
+        self.node_mut(index).next = right;
+        if next != NIL {
+            self.node_mut(next).prev = right;
+        }
+        (separator, right)
+    }
+    fn split_internal(&mut self, index: Index) -> (K, Index) {
+        let node = self.node_mut(index);
+        let left_len = node.children.len().div_ceil(2);
+        let children = node.children.split_off(left_len);
+        let mut keys = node.keys.split_off(left_len - 1);
+        let separator = keys.remove(0);
+        let right = self.alloc(Node {
+            keys,
+            values: vec![],
+            children,
+            prev: NIL,
+            next: NIL,
+        });
+        (separator, right)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if self.root == NIL {
+            return None;
+        }
+        let root = self.root;
+        let removed = self.remove_r(root, key)?;
+        let root_node = self.node(root);
+        if root_node.is_leaf() && root_node.keys.is_empty() {
+            self.dealloc(root);
+            self.root = NIL;
+        } else if root_node.children.len() == 1 {
+            self.root = root_node.children[0];
+            self.dealloc(root);
+        }
+        Some(removed)
+    }
+    fn remove_r(&mut self, index: Index, key: &K) -> Option<V> {
+        let node = self.node_mut(index);
+        if node.is_leaf() {
+            let found = node.keys.binary_search(key).ok()?;
+            node.keys.remove(found);
+            self.length -= 1;
+            return Some(self.node_mut(index).values.remove(found));
+        }
+        // 分隔键即使被删除也仍然能分隔左右子树，不需要更新
+        let child_index = node.child_index(key);
+        let child = node.children[child_index];
+        let removed = self.remove_r(child, key)?;
+        self.fix_on_child_removed(index, child_index);
+        Some(removed)
+    }
+    fn fix_on_child_removed(&mut self, parent: Index, child_index: usize) {
+        let min_children = self.max_children_length / 2;
+        let children = &self.node(parent).children;
+        if self.node(children[child_index]).children_len() >= min_children {
+            return;
+        }
+
+        if child_index > 0 && self.node(children[child_index - 1]).children_len() > min_children {
+            self.move_to_right(parent, child_index - 1);
+        } else if child_index + 1 < children.len()
+            && self.node(children[child_index + 1]).children_len() > min_children
+        {
+            self.move_to_left(parent, child_index);
+        } else {
+            self.merge(parent, child_index.saturating_sub(1));
+        }
+    }
+    // 把 separator 左侧子节点的最后一项移到右侧子节点
+    fn move_to_right(&mut self, parent: Index, separator: usize) {
+        let (left, right) = {
+            let children = &self.node(parent).children;
+            (children[separator], children[separator + 1])
+        };
+        let left_node = self.node_mut(left);
+        if left_node.is_leaf() {
+            let key = left_node.keys.pop().unwrap();
+            let value = left_node.values.pop().unwrap();
+            self.node_mut(parent).keys[separator] = key.clone();
+            let right_node = self.node_mut(right);
+            right_node.keys.insert(0, key);
+            right_node.values.insert(0, value);
+        } else {
+            let key = left_node.keys.pop().unwrap();
+            let child = left_node.children.pop().unwrap();
+            let key = std::mem::replace(&mut self.node_mut(parent).keys[separator], key);
+            let right_node = self.node_mut(right);
+            right_node.keys.insert(0, key);
+            right_node.children.insert(0, child);
+        }
+    }
+    // 把 separator 右侧子节点的第一项移到左侧子节点
+    fn move_to_left(&mut self, parent: Index, separator: usize) {
+        let (left, right) = {
+            let children = &self.node(parent).children;
+            (children[separator], children[separator + 1])
+        };
+        let right_node = self.node_mut(right);
+        if right_node.is_leaf() {
+            let key = right_node.keys.remove(0);
+            let value = right_node.values.remove(0);
+            let new_separator = right_node.keys[0].clone();
+            self.node_mut(parent).keys[separator] = new_separator;
+            let left_node = self.node_mut(left);
+            left_node.keys.push(key);
+            left_node.values.push(value);
+        } else {
+            let key = right_node.keys.remove(0);
+            let child = right_node.children.remove(0);
+            let key = std::mem::replace(&mut self.node_mut(parent).keys[separator], key);
+            let left_node = self.node_mut(left);
+            left_node.keys.push(key);
+            left_node.children.push(child);
+        }
+    }
+    // 合并 separator 两侧的子节点
+    fn merge(&mut self, parent: Index, separator: usize) {
+        let parent_node = self.node_mut(parent);
+        let key = parent_node.keys.remove(separator);
+        let right = parent_node.children.remove(separator + 1);
+        let left = parent_node.children[separator];
+
+        let right_node = std::mem::replace(self.node_mut(right), Node::new_leaf());
+        self.dealloc(right);
+        let left_node = self.node_mut(left);
+        if left_node.is_leaf() {
+            left_node.keys.extend(right_node.keys);
+            left_node.values.extend(right_node.values);
+            left_node.next = right_node.next;
+            if right_node.next != NIL {
+                self.node_mut(right_node.next).prev = left;
+            }
+        } else {
+            left_node.keys.push(key);
+            left_node.keys.extend(right_node.keys);
+            left_node.children.extend(right_node.children);
+        }
+    }
+}
+
+// 沿叶子链表在两个位置之间遍历，不需要保存路径栈
+pub struct Range<'t, K: Ord, V> {
+    tree: &'t BPlusTree<K, V>,
+    // front 为下一个返回的键值对，back 为最后一个返回的键值对之后
+    front: (Index, usize),
+    back: (Index, usize),
+}
+
+impl<'t, K: Ord, V> Iterator for Range<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let (leaf, index) = self.front;
+        let node = self.tree.node(leaf);
+        self.front = self.tree.canonical((leaf, index + 1));
+        Some((&node.keys[index], &node.values[index]))
+    }
+}
+
+impl<'t, K: Ord, V> DoubleEndedIterator for Range<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let (leaf, index) = self.back;
+        self.back = match index.checked_sub(1) {
+            Some(index) => (leaf, index),
+            None => {
+                let prev = self.tree.node(leaf).prev;
+                (prev, self.tree.node(prev).keys.len() - 1)
+            }
+        };
+        let (leaf, index) = self.back;
+        let node = self.tree.node(leaf);
+        Some((&node.keys[index], &node.values[index]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    use rand::Rng;
+
+    use crate::b_plus_tree::*;
+
+    fn random_bound(rng: &mut impl Rng) -> Bound<i32> {
+        match rng.gen_range(0..3) {
+            0 => Bound::Included(rng.gen_range(-10..1010)),
+            1 => Bound::Excluded(rng.gen_range(-10..1010)),
+            _ => Bound::Unbounded,
+        }
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut rng = rand::thread_rng();
+        for &max_children_length in &[4, 5, 16] {
+            let mut tree = BPlusTree::new(max_children_length);
+            let mut std_tree = BTreeMap::new();
+            for _ in 0..5000 {
+                let key = rng.gen_range(0..500);
+                if rng.gen_bool(0.4) {
+                    assert_eq!(tree.remove(&key), std_tree.remove(&key));
+                } else {
+                    assert_eq!(tree.add(key, key * 2), std_tree.insert(key, key * 2));
+                }
+                assert_eq!(tree.validate(), Ok(()));
+                assert_eq!(tree.get(&key), std_tree.get(&key));
+                assert_eq!(tree.len(), std_tree.len());
+            }
+            if let Some(value) = tree.get_mut(&std_tree.keys().next().copied().unwrap()) {
+                *value = -1;
+            }
+            *std_tree.values_mut().next().unwrap() = -1;
+            assert!(tree.iter().eq(std_tree.iter()));
+
+            for key in 0..500 {
+                tree.remove(&key);
+            }
+            assert!(tree.is_empty());
+            assert_eq!(tree.root, NIL);
+            assert_eq!(tree.iter().count(), 0);
+        }
+    }
+
+    #[test]
+    fn range() {
+        let mut rng = rand::thread_rng();
+        let mut tree = BPlusTree::new(6);
+        let mut std_tree = BTreeMap::new();
+        for _ in 0..1000 {
+            let key = rng.gen_range(0..500) * 2;
+            tree.add(key, -key);
+            std_tree.insert(key, -key);
+        }
+
+        for _ in 0..2000 {
+            let bounds = (random_bound(&mut rng), random_bound(&mut rng));
+            // std 在这些情况下会 panic，这里返回空的范围
+            let std_panics = match bounds {
+                (
+                    Bound::Included(start) | Bound::Excluded(start),
+                    Bound::Included(end) | Bound::Excluded(end),
+                ) if start > end => true,
+                (Bound::Excluded(start), Bound::Excluded(end)) => start == end,
+                _ => false,
+            };
+            if std_panics {
+                assert_eq!(tree.range(bounds).count(), 0);
+                continue;
+            }
+            assert!(tree.range(bounds).eq(std_tree.range(bounds)));
+            assert!(tree.range(bounds).rev().eq(std_tree.range(bounds).rev()));
+
+            let mut range = tree.range(bounds);
+            let mut std_range = std_tree.range(bounds);
+            loop {
+                let (pair, std_pair) = if rng.gen_bool(0.5) {
+                    (range.next(), std_range.next())
+                } else {
+                    (range.next_back(), std_range.next_back())
+                };
+                assert_eq!(pair, std_pair);
+                if std_pair.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let mut tree = BPlusTree::new(4);
+        for i in 0..20 {
+            tree.add(i, i);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let mut leaf = tree.root;
+        while !tree.node(leaf).is_leaf() {
+            leaf = tree.node(leaf).children[0];
+        }
+        let next = tree.node(leaf).next;
+        tree.node_mut(leaf).next = NIL;
+        assert!(matches!(
+            tree.validate(),
+            Err(InvariantViolation::LeafLink { .. })
+        ));
+        tree.node_mut(leaf).next = next;
+
+        tree.node_mut(leaf).keys[0] = 100;
+        assert!(matches!(
+            tree.validate(),
+            Err(InvariantViolation::KeyOrder { .. })
+        ));
+    }
+}
@@ -1,8 +1,14 @@
 use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::ops::{Bound, Deref, RangeBounds};
 use std::option::Option::None;
+use std::sync::Arc;
+
+use crate::invariant::InvariantViolation;
 
 type Pair<K, V> = (K, V);
-type Tree<K, V> = Box<Node<K, V>>;
+// 节点可被多个快照共享，修改时写时复制
+type Tree<K, V> = Arc<Node<K, V>>;
 
 struct SplitInfo<K: Ord, V> {
     mid_pair: Pair<K, V>,
@@ -18,6 +24,7 @@ impl<K: Ord, V> SplitInfo<K, V> {
     }
 }
 
+#[derive(Clone)]
 struct Node<K: Ord, V> {
     pairs: Vec<Pair<K, V>>,
     children: Vec<Tree<K, V>>,
@@ -25,21 +32,21 @@ struct Node<K: Ord, V> {
 
 impl<K: Ord, V> Node<K, V> {
     fn new_leaf() -> Tree<K, V> {
-        Box::new(Self {
+        Arc::new(Self {
             pairs: vec![],
             children: vec![],
         })
     }
 
     fn new_with_data(pairs: Vec<Pair<K, V>>, children: Option<Vec<Tree<K, V>>>) -> Tree<K, V> {
-        Box::new(Self {
+        Arc::new(Self {
             pairs,
-            children: children.unwrap_or_else(Vec::default),
+            children: children.unwrap_or_default(),
         })
     }
 
     fn new_root(pair: Pair<K, V>, left_child: Tree<K, V>, right_child: Tree<K, V>) -> Tree<K, V> {
-        Box::new(Self {
+        Arc::new(Self {
             pairs: vec![pair],
             children: vec![left_child, right_child],
         })
@@ -52,62 +59,441 @@ impl<K: Ord, V> Node<K, V> {
         pair: Pair<K, V>,
         right_child: Option<Tree<K, V>>,
     ) -> Option<SplitInfo<K, V>> {
-        if self.pairs.len() + 1 >= max_children_length {
-            let new_pair_index = (self.pairs.len() + 1) / 2;
-            let (mid_pair, right_pairs, right_children) = match to_insert.cmp(&new_pair_index) {
-                Ordering::Less => {
-                    let right_pairs = self.pairs.split_off(new_pair_index);
-                    let mid_pair = self.pairs.pop().unwrap();
-                    self.pairs.insert(to_insert, pair);
-                    let right_children = right_child.map(|right_child| {
-                        let right_children = self.children.split_off(new_pair_index);
-                        self.children.insert(to_insert + 1, right_child);
-                        right_children
-                    });
-                    (mid_pair, right_pairs, right_children)
+        add_pair(self, max_children_length, to_insert, pair, right_child)
+            .map(|(mid_pair, right)| SplitInfo::new(mid_pair, Arc::new(right)))
+    }
+}
+
+impl<K: Ord, V> Branch for Node<K, V> {
+    type Pair = Pair<K, V>;
+    type Child = Tree<K, V>;
+
+    fn from_parts(pairs: Vec<Self::Pair>, children: Vec<Self::Child>) -> Self {
+        Self { pairs, children }
+    }
+    fn parts(&mut self) -> (&mut Vec<Self::Pair>, &mut Vec<Self::Child>) {
+        (&mut self.pairs, &mut self.children)
+    }
+}
+
+// 节点的键值对与子节点，内存中的和分页存储的 B 树共用分裂、借用与合并的算法
+pub(crate) trait Branch: Sized {
+    type Pair;
+    type Child;
+
+    fn from_parts(pairs: Vec<Self::Pair>, children: Vec<Self::Child>) -> Self;
+    fn parts(&mut self) -> (&mut Vec<Self::Pair>, &mut Vec<Self::Child>);
+}
+
+// 插入键值对，节点已满时分裂，返回被提升的中间键值对和右半部分
+pub(crate) fn add_pair<N: Branch>(
+    node: &mut N,
+    max_children_length: usize,
+    to_insert: usize,
+    pair: N::Pair,
+    right_child: Option<N::Child>,
+) -> Option<(N::Pair, N)> {
+    let (pairs, children) = node.parts();
+    if pairs.len() + 1 >= max_children_length {
+        let new_pair_index = pairs.len().div_ceil(2);
+        let (mid_pair, right_pairs, right_children) = match to_insert.cmp(&new_pair_index) {
+            Ordering::Less => {
+                let right_pairs = pairs.split_off(new_pair_index);
+                let mid_pair = pairs.pop().unwrap();
+                pairs.insert(to_insert, pair);
+                let right_children = right_child.map(|right_child| {
+                    let right_children = children.split_off(new_pair_index);
+                    children.insert(to_insert + 1, right_child);
+                    right_children
+                });
+                (mid_pair, right_pairs, right_children)
+            }
+            Ordering::Equal => {
+                let right_pairs = pairs.split_off(new_pair_index);
+                let right_children = right_child.map(|right_child| {
+                    let mut right_children = children.split_off(new_pair_index + 1);
+                    right_children.insert(0, right_child);
+                    right_children
+                });
+                (pair, right_pairs, right_children)
+            }
+            Ordering::Greater => {
+                let mut right_pairs = pairs.split_off(new_pair_index + 1);
+                let mid_pair = pairs.pop().unwrap();
+                let to_insert = to_insert - (new_pair_index + 1);
+                right_pairs.insert(to_insert, pair);
+                let right_children = right_child.map(|right_child| {
+                    let mut right_children = children.split_off(new_pair_index + 1);
+                    right_children.insert(to_insert + 1, right_child);
+                    right_children
+                });
+                (mid_pair, right_pairs, right_children)
+            }
+        };
+        Some((
+            mid_pair,
+            N::from_parts(right_pairs, right_children.unwrap_or_default()),
+        ))
+    } else {
+        pairs.insert(to_insert, pair);
+        if let Some(right_child) = right_child {
+            children.insert(to_insert + 1, right_child);
+        }
+        None
+    }
+}
+
+// 子节点删除键值对后的修复方式
+pub(crate) enum Rebalance {
+    None,
+    BorrowLeft,
+    BorrowRight,
+    // 合并该位置的子节点与其右兄弟
+    Merge(usize),
+}
+
+// 键值对过少时优先向兄弟借用，否则与较小的兄弟合并
+pub(crate) fn plan_rebalance(
+    min_pairs_len: usize,
+    child_index: usize,
+    child_len: usize,
+    left_len: Option<usize>,
+    right_len: Option<usize>,
+) -> Rebalance {
+    if child_len >= min_pairs_len {
+        return Rebalance::None;
+    }
+    if left_len.is_some_and(|len| len > min_pairs_len) {
+        return Rebalance::BorrowLeft;
+    }
+    if right_len.is_some_and(|len| len > min_pairs_len) {
+        return Rebalance::BorrowRight;
+    }
+    match (left_len, right_len) {
+        (Some(left_len), Some(right_len)) if left_len < right_len => {
+            Rebalance::Merge(child_index - 1)
+        }
+        (_, Some(_)) => Rebalance::Merge(child_index),
+        (Some(_), None) => Rebalance::Merge(child_index - 1),
+        (None, None) => unreachable!(),
+    }
+}
+
+// 左兄弟的最后一个键值对经父节点移入 child
+pub(crate) fn borrow_from_left<N: Branch>(separator: &mut N::Pair, left: &mut N, child: &mut N) {
+    let (left_pairs, left_children) = left.parts();
+    let (pairs, children) = child.parts();
+    pairs.insert(0, std::mem::replace(separator, left_pairs.pop().unwrap()));
+    if let Some(left_child_right) = left_children.pop() {
+        children.insert(0, left_child_right);
+    }
+}
+
+// 右兄弟的第一个键值对经父节点移入 child
+pub(crate) fn borrow_from_right<N: Branch>(separator: &mut N::Pair, child: &mut N, right: &mut N) {
+    let (right_pairs, right_children) = right.parts();
+    let (pairs, children) = child.parts();
+    pairs.push(std::mem::replace(separator, right_pairs.remove(0)));
+    if !right_children.is_empty() {
+        children.push(right_children.remove(0));
+    }
+}
+
+// 左兄弟的最后 count 个键值对经父节点移入 child
+fn bulk_steal_left<N: Branch>(separator: &mut N::Pair, left: &mut N, child: &mut N, count: usize) {
+    let (left_pairs, left_children) = left.parts();
+    let (pairs, children) = child.parts();
+    let mut moved = left_pairs.split_off(left_pairs.len() - count);
+    let new_separator = moved.remove(0);
+    moved.push(std::mem::replace(separator, new_separator));
+    pairs.splice(0..0, moved);
+    if !left_children.is_empty() {
+        let moved_children = left_children.split_off(left_children.len() - count);
+        children.splice(0..0, moved_children);
+    }
+}
+
+// 右兄弟的前 count 个键值对经父节点移入 child
+fn bulk_steal_right<N: Branch>(
+    separator: &mut N::Pair,
+    child: &mut N,
+    right: &mut N,
+    count: usize,
+) {
+    let (right_pairs, right_children) = right.parts();
+    let (pairs, children) = child.parts();
+    let mut moved = right_pairs.drain(..count).collect::<Vec<_>>();
+    let new_separator = moved.pop().unwrap();
+    pairs.push(std::mem::replace(separator, new_separator));
+    pairs.append(&mut moved);
+    if !right_children.is_empty() {
+        children.extend(right_children.drain(..count));
+    }
+}
+
+pub(crate) fn merge<N: Branch>(separator: N::Pair, left: &mut N, mut right: N) {
+    let (right_pairs, right_children) = right.parts();
+    let (pairs, children) = left.parts();
+    pairs.push(separator);
+    pairs.append(right_pairs);
+    children.append(right_children);
+}
+
+// 键值对位于分裂后的左半部分、被提升的中间键值对或右半部分，不分裂时视为左半部分
+enum Side {
+    Left,
+    Middle,
+    Right,
+}
+
+// 在插入后、分裂前的节点中，下标为 index 的键值对分裂后的位置
+fn place_pair(pairs_len: usize, max_children_length: usize, index: usize) -> (Side, usize) {
+    if pairs_len + 1 < max_children_length {
+        return (Side::Left, index);
+    }
+    let mid = pairs_len.div_ceil(2);
+    match index.cmp(&mid) {
+        Ordering::Less => (Side::Left, index),
+        Ordering::Equal => (Side::Middle, 0),
+        Ordering::Greater => (Side::Right, index - mid - 1),
+    }
+}
+
+// 在插入后、分裂前的节点中，下标为 index 的子节点分裂后的位置
+fn place_child(pairs_len: usize, max_children_length: usize, index: usize) -> (Side, usize) {
+    let mid = pairs_len.div_ceil(2);
+    if pairs_len + 1 < max_children_length || index <= mid {
+        (Side::Left, index)
+    } else {
+        (Side::Right, index - mid - 1)
+    }
+}
+
+// 流式地自底向上装填节点：每层只保留正在装填的节点 open 和上一个已装满的节点 done，
+// done 等到 open 也装满时才交给上一层，结束时 open 可能不足半满，再与 done 重新分配
+struct Loader<K: Ord, V> {
+    max_children_length: usize,
+    // 每个节点装填的子节点个数
+    target: usize,
+    // 下标 0 为叶子节点所在的层
+    levels: Vec<LoaderLevel<K, V>>,
+}
+
+struct LoaderLevel<K: Ord, V> {
+    open: Node<K, V>,
+    // 已装满的节点及它与 open 之间的键值对
+    done: Option<(Node<K, V>, Pair<K, V>)>,
+}
+
+impl<K: Ord, V> LoaderLevel<K, V> {
+    fn new(open: Node<K, V>) -> Self {
+        Self { open, done: None }
+    }
+}
+
+impl<K: Ord, V> Loader<K, V> {
+    fn new(max_children_length: usize, fill_factor: f64) -> Self {
+        assert!(fill_factor > 0.0 && fill_factor <= 1.0);
+        let target = ((max_children_length as f64 * fill_factor).round() as usize)
+            .clamp(max_children_length / 2, max_children_length);
+        Self {
+            max_children_length,
+            target,
+            levels: vec![],
+        }
+    }
+    // 接着已有的树继续装填，最右侧路径上的节点作为各层的 open，它们的最后一个子节点由下一层装填完后放回
+    fn from_right_spine(max_children_length: usize, fill_factor: f64, root: Tree<K, V>) -> Self
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut loader = Self::new(max_children_length, fill_factor);
+        let mut tree = root;
+        loop {
+            let mut node = Arc::unwrap_or_clone(tree);
+            let last = node.children.pop();
+            loader.levels.push(LoaderLevel::new(node));
+            match last {
+                None => break,
+                Some(last) => tree = last,
+            }
+        }
+        loader.levels.reverse();
+        loader
+    }
+
+    // 叶子节点按键值对个数加 1 计算子节点个数
+    fn children_len(level: usize, node: &Node<K, V>) -> usize {
+        if level == 0 {
+            node.pairs.len() + 1
+        } else {
+            node.children.len()
+        }
+    }
+    fn level_mut(&mut self, level: usize) -> &mut LoaderLevel<K, V> {
+        if level == self.levels.len() {
+            self.levels
+                .push(LoaderLevel::new(Node::from_parts(vec![], vec![])));
+        }
+        &mut self.levels[level]
+    }
+
+    fn push_child(&mut self, level: usize, child: Node<K, V>) {
+        self.level_mut(level).open.children.push(Arc::new(child));
+    }
+    // open 已装满时，pair 成为它与下一个节点之间的键值对，此时上一个装满的节点交给上一层
+    fn push_pair(&mut self, level: usize, pair: Pair<K, V>) {
+        let target = self.target;
+        let current = self.level_mut(level);
+        if Self::children_len(level, &current.open) < target {
+            current.open.pairs.push(pair);
+            return;
+        }
+        let full = std::mem::replace(&mut current.open, Node::from_parts(vec![], vec![]));
+        if let Some((done, separator)) = current.done.replace((full, pair)) {
+            self.push_child(level + 1, done);
+            self.push_pair(level + 1, separator);
+        }
+    }
+
+    // 装填按键升序排列的键值对，键相同时保留后一个，返回装填的个数
+    fn load<I: IntoIterator<Item = Pair<K, V>>>(&mut self, iter: I) -> usize {
+        let mut count = 0;
+        let mut last: Option<Pair<K, V>> = None;
+        for pair in iter {
+            if let Some(last) = last.take() {
+                if last.0 != pair.0 {
+                    assert!(last.0 < pair.0, "keys are not sorted");
+                    self.push_pair(0, last);
+                    count += 1;
                 }
-                Ordering::Equal => {
-                    let right_pairs = self.pairs.split_off(new_pair_index);
-                    let right_children = right_child.map(|right_child| {
-                        let mut right_children = self.children.split_off(new_pair_index + 1);
-                        right_children.insert(0, right_child);
-                        right_children
-                    });
-                    (pair, right_pairs, right_children)
-                }
-                Ordering::Greater => {
-                    let mut right_pairs = self.pairs.split_off(new_pair_index + 1);
-                    let mid_pair = self.pairs.pop().unwrap();
-                    let to_insert = to_insert - (new_pair_index + 1);
-                    right_pairs.insert(to_insert, pair);
-                    let right_children = right_child.map(|right_child| {
-                        let mut right_children = self.children.split_off(new_pair_index + 1);
-                        right_children.insert(to_insert + 1, right_child);
-                        right_children
-                    });
-                    (mid_pair, right_pairs, right_children)
+            }
+            last = Some(pair);
+        }
+        if let Some(last) = last {
+            self.push_pair(0, last);
+            count += 1;
+        }
+        count
+    }
+
+    fn finish(mut self) -> Option<Tree<K, V>> {
+        if self.levels.is_empty() {
+            return None;
+        }
+        let mut level = 0;
+        loop {
+            let is_top = level + 1 == self.levels.len();
+            let LoaderLevel { mut open, done } = std::mem::replace(
+                &mut self.levels[level],
+                LoaderLevel::new(Node::from_parts(vec![], vec![])),
+            );
+            match done {
+                None if is_top => {
+                    // 只有一个子节点时由它作为根节点
+                    return if open.pairs.is_empty() {
+                        open.children.pop()
+                    } else {
+                        Some(Arc::new(open))
+                    };
+                }
+                None => self.push_child(level + 1, open),
+                Some((done, separator))
+                    if Self::children_len(level, &open) >= self.max_children_length / 2 =>
+                {
+                    self.push_child(level + 1, done);
+                    self.push_pair(level + 1, separator);
+                    self.push_child(level + 1, open);
+                }
+                Some((mut done, separator)) => {
+                    // open 不足半满，与 done 合并，一个节点放不下时再平分成两个
+                    merge(separator, &mut done, open);
+                    let total = Self::children_len(level, &done);
+                    if total <= self.max_children_length {
+                        self.push_child(level + 1, done);
+                    } else {
+                        let left_len = total / 2;
+                        let right_pairs = done.pairs.split_off(left_len);
+                        let right_children = if level == 0 {
+                            vec![]
+                        } else {
+                            done.children.split_off(left_len)
+                        };
+                        let separator = done.pairs.pop().unwrap();
+                        self.push_child(level + 1, done);
+                        self.push_pair(level + 1, separator);
+                        self.push_child(level + 1, Node::from_parts(right_pairs, right_children));
+                    }
                 }
-            };
-            Some(SplitInfo::new(
-                mid_pair,
-                Node::new_with_data(right_pairs, right_children),
-            ))
-        } else {
-            self.pairs.insert(to_insert, pair);
-            if let Some(right_child) = right_child {
-                self.children.insert(to_insert + 1, right_child);
             }
-            None
+            level += 1;
         }
     }
 }
 
+// append 需要整体重建时节点的填充率，为之后的插入留出空间
+const REBUILD_FILL_FACTOR: f64 = 0.75;
+
+// 新插入的键值对的位置，path 为从 side 所指节点出发的子节点下标，逆序保存
+struct Placement {
+    side: Side,
+    path: Vec<usize>,
+    index: usize,
+}
+
+fn height<K: Ord, V>(tree: Option<&Tree<K, V>>) -> usize {
+    let mut height = 0;
+    let mut tree = tree;
+    while let Some(node) = tree {
+        height += 1;
+        tree = node.children.first();
+    }
+    height
+}
+
+fn count_pairs<K: Ord, V>(tree: Option<&Tree<K, V>>) -> usize {
+    tree.map_or(0, |node| {
+        node.pairs.len()
+            + node
+                .children
+                .iter()
+                .map(|child| count_pairs(Some(child)))
+                .sum::<usize>()
+    })
+}
+
 pub struct BTree<K: Ord, V> {
     root: Option<Tree<K, V>>,
     max_children_length: usize,
     length: usize,
 }
 
+// 只复制根节点的引用
+impl<K: Ord, V> Clone for BTree<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            max_children_length: self.max_children_length,
+            length: self.length,
+        }
+    }
+}
+
+// snapshot 得到的 BTree 只读视图
+#[derive(Clone)]
+pub struct Snapshot<K: Ord, V> {
+    tree: BTree<K, V>,
+}
+
+impl<K: Ord, V> Deref for Snapshot<K, V> {
+    type Target = BTree<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tree
+    }
+}
+
 impl<K: Ord, V> Default for BTree<K, V> {
     fn default() -> Self {
         Self::new(256)
@@ -123,6 +509,20 @@ impl<K: Ord, V> BTree<K, V> {
             length: 0,
         }
     }
+    // 由按键升序的键值对自底向上在 O(n) 时间内建树，键相同时后出现的键值对覆盖先出现的，
+    // 节点约装填到容量的 fill_factor。边读入边装填，只额外保存正在装填的节点
+    pub fn from_sorted_iter<I: IntoIterator<Item = Pair<K, V>>>(
+        max_children_length: usize,
+        fill_factor: f64,
+        iter: I,
+    ) -> Self {
+        let mut tree = Self::new(max_children_length);
+        let mut loader = Loader::new(max_children_length, fill_factor);
+        tree.length = loader.load(iter);
+        tree.root = loader.finish();
+        tree
+    }
+
     pub fn len(&self) -> usize {
         self.length
     }
@@ -131,38 +531,526 @@ impl<K: Ord, V> BTree<K, V> {
     }
 
     pub fn is_valid(&self) -> bool {
-        match self.root.as_ref() {
-            None => true,
-            Some(root) => self.validate(root, 0).is_ok(),
+        self.validate().is_ok()
+    }
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let count = match self.root.as_ref() {
+            None => 0,
+            Some(root) => self.validate_node(root, None, None, &mut vec![], &mut None)?,
+        };
+        if count != self.length {
+            return Err(InvariantViolation::Length {
+                recorded: self.length,
+                counted: count,
+            });
         }
+        Ok(())
     }
-    fn validate(&self, node: &Tree<K, V>, node_level: usize) -> Result<usize, ()> {
-        let mut children = node.children.iter();
-        if let Some(child) = children.next() {
-            if node.children.len() > self.max_children_length {
-                return Err(());
+    // 返回子树中键值对的个数，path 为从根节点到 node 所经过的子节点下标
+    fn validate_node(
+        &self,
+        node: &Tree<K, V>,
+        min: Option<&K>,
+        max: Option<&K>,
+        path: &mut Vec<usize>,
+        leaf_depth: &mut Option<usize>,
+    ) -> Result<usize, InvariantViolation> {
+        let is_leaf = node.children.is_empty();
+        if !is_leaf && node.children.len() != node.pairs.len() + 1 {
+            return Err(InvariantViolation::ChildCount {
+                path: path.clone(),
+                pairs: node.pairs.len(),
+                children: node.children.len(),
+            });
+        }
+        // 叶子节点按键值对个数加 1 计算
+        let children = node.pairs.len() + 1;
+        let min_children = if path.is_empty() {
+            2
+        } else {
+            self.max_children_length / 2
+        };
+        if children < min_children || children > self.max_children_length {
+            return Err(InvariantViolation::FanOut {
+                path: path.clone(),
+                children,
+                min: min_children,
+                max: self.max_children_length,
+            });
+        }
+
+        let mut prev = min;
+        for (key, _value) in node.pairs.iter() {
+            if prev.is_some_and(|prev| key <= prev) {
+                return Err(InvariantViolation::KeyOrder { path: path.clone() });
             }
-            let min_children_len = if node_level > 0 {
-                self.max_children_length / 2
-            } else {
-                2
+            prev = Some(key);
+        }
+        if max.is_some_and(|max| prev.is_some_and(|last| last >= max)) {
+            return Err(InvariantViolation::KeyOrder { path: path.clone() });
+        }
+
+        if is_leaf {
+            return match *leaf_depth {
+                None => {
+                    *leaf_depth = Some(path.len());
+                    Ok(node.pairs.len())
+                }
+                Some(expected) if expected != path.len() => Err(InvariantViolation::LeafDepth {
+                    path: path.clone(),
+                    depth: path.len(),
+                    expected,
+                }),
+                Some(_) => Ok(node.pairs.len()),
             };
-            if node.children.len() < min_children_len {
-                return Err(());
+        }
+        let mut count = node.pairs.len();
+        for (index, child) in node.children.iter().enumerate() {
+            let child_min = index.checked_sub(1).map_or(min, |i| Some(&node.pairs[i].0));
+            let child_max = node.pairs.get(index).map_or(max, |(key, _value)| Some(key));
+            path.push(index);
+            count += self.validate_node(child, child_min, child_max, path, leaf_depth)?;
+            path.pop();
+        }
+        Ok(count)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut node = self.root.as_ref()?;
+        loop {
+            match node.pairs.binary_search_by_key(&key, |(key, _value)| key) {
+                Ok(found) => return Some(&node.pairs[found].1),
+                Err(child_index) => node = node.children.get(child_index)?,
+            }
+        }
+    }
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(first_child) = node.children.first() {
+            node = first_child;
+        }
+        node.pairs.first().map(|(key, value)| (key, value))
+    }
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(last_child) = node.children.last() {
+            node = last_child;
+        }
+        node.pairs.last().map(|(key, value)| (key, value))
+    }
+
+    fn node_at(&self, path: &[usize]) -> &Tree<K, V> {
+        let mut node = self.root.as_ref().unwrap();
+        for &child_index in path {
+            node = &node.children[child_index];
+        }
+        node
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            traversal: Traversal::new(self.root.as_ref()),
+            remain_len: self.length,
+        }
+    }
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.iter() }
+    }
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.iter() }
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let mut traversal = Traversal::new(None);
+        let (lower, upper) = (range.start_bound(), range.end_bound());
+        let is_empty = match (lower, upper) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) => start >= end,
+            _ => false,
+        };
+        if let (Some(root), false) = (self.root.as_ref(), is_empty) {
+            traversal.seek(root, lower, upper);
+        }
+        Range { traversal }
+    }
+
+    // O(1) 得到当前内容的只读视图，之后对 self 的修改在视图中不可见
+    pub fn snapshot(&self) -> Snapshot<K, V> {
+        Snapshot { tree: self.clone() }
+    }
+
+    // 键在任一棵树中的键值对，两棵树都有的键取 self 的键值对
+    pub fn union<'t>(&'t self, other: &'t Self) -> Union<'t, K, V> {
+        Union {
+            merge: MergeIter::new(self, other),
+        }
+    }
+    // self 中键也在 other 中的键值对
+    pub fn intersection<'t>(&'t self, other: &'t Self) -> Intersection<'t, K, V> {
+        Intersection {
+            merge: MergeIter::new(self, other),
+        }
+    }
+    // self 中键不在 other 中的键值对
+    pub fn difference<'t>(&'t self, other: &'t Self) -> Difference<'t, K, V> {
+        Difference {
+            merge: MergeIter::new(self, other),
+        }
+    }
+    // 键恰好只在一棵树中的键值对
+    pub fn symmetric_difference<'t>(&'t self, other: &'t Self) -> SymmetricDifference<'t, K, V> {
+        SymmetricDifference {
+            merge: MergeIter::new(self, other),
+        }
+    }
+}
+
+// 节点被共享时，修改前先复制，只有被修改的路径上的节点会被复制
+impl<K: Ord + Clone, V: Clone> BTree<K, V> {
+    // 加入按键升序的键值对，iter 中的值覆盖已有的值。它们都大于已有的键时沿最右侧路径追加，
+    // 耗时 O(m + log n)，否则与原有的键值对归并后像 from_sorted_iter 一样重建，耗时 O(n + m)
+    pub fn extend_sorted<I: IntoIterator<Item = Pair<K, V>>>(&mut self, fill_factor: f64, iter: I) {
+        let max_children_length = self.max_children_length;
+        let mut new = iter.into_iter().peekable();
+        let appends = match (self.last_key_value(), new.peek()) {
+            (_, None) => return,
+            (None, Some(_)) => true,
+            (Some((last, _)), Some((first, _))) => last < first,
+        };
+        if appends {
+            let mut loader = match self.root.take() {
+                None => Loader::new(max_children_length, fill_factor),
+                Some(root) => Loader::from_right_spine(max_children_length, fill_factor, root),
             };
+            self.length += loader.load(new);
+            self.root = loader.finish();
+            return;
+        }
 
-            let next_level = node_level + 1;
-            let child_level = self.validate(child, next_level)?;
-            for child in children {
-                if self.validate(child, next_level)? != child_level {
-                    return Err(());
+        let mut old = std::mem::replace(self, Self::new(max_children_length))
+            .into_iter()
+            .peekable();
+        let merged = std::iter::from_fn(|| match (old.peek(), new.peek()) {
+            (Some(old_pair), Some(new_pair)) => match old_pair.0.cmp(&new_pair.0) {
+                Ordering::Less => old.next(),
+                Ordering::Equal => {
+                    old.next();
+                    new.next()
                 }
+                Ordering::Greater => new.next(),
+            },
+            (Some(_), None) => old.next(),
+            (None, _) => new.next(),
+        });
+        *self = Self::from_sorted_iter(max_children_length, fill_factor, merged);
+    }
+
+    // 把键不小于 key 的键值对移到新树中。沿查找路径在 O(log n) 时间内把树切成两棵，
+    // 再数出较矮的那棵树的键值对个数
+    pub fn split_off(&mut self, key: &K) -> Self {
+        let mut right = Self::new(self.max_children_length);
+        let mut node = match self.root.as_mut() {
+            None => return right,
+            Some(root) => Arc::make_mut(root),
+        };
+        // 沿查找路径把每个节点切成两半，右半部分自顶向下组成新树的最左侧路径
+        let mut right_border = vec![];
+        loop {
+            let index = match node
+                .pairs
+                .binary_search_by(|(pair_key, _value)| pair_key.cmp(key))
+            {
+                Ok(index) | Err(index) => index,
+            };
+            let pairs = node.pairs.split_off(index);
+            let children = if node.children.is_empty() {
+                vec![]
+            } else {
+                node.children.split_off(index + 1)
+            };
+            right_border.push(Node::from_parts(pairs, children));
+            match node.children.last_mut() {
+                None => break,
+                Some(child) => node = Arc::make_mut(child),
+            }
+        }
+        for mut border in right_border.into_iter().rev() {
+            if let Some(child) = right.root.take() {
+                border.children.insert(0, child);
             }
-            Ok(child_level)
+            right.root = Some(Arc::new(border));
+        }
+        self.fix_right_border();
+        right.fix_left_border();
+
+        let total = self.length;
+        if height(self.root.as_ref()) <= height(right.root.as_ref()) {
+            self.length = count_pairs(self.root.as_ref());
+            right.length = total - self.length;
         } else {
-            // 无子节点说明自己是叶子节点
-            Ok(node_level)
+            right.length = count_pairs(right.root.as_ref());
+            self.length = total - right.length;
+        }
+        right
+    }
+    // 去掉没有键值对的根节点
+    fn fix_top(&mut self) {
+        while let Some(root) = self.root.take() {
+            if !root.pairs.is_empty() {
+                self.root = Some(root);
+                return;
+            }
+            self.root = Arc::unwrap_or_clone(root).children.pop();
+        }
+    }
+    // 切开后右边界上的节点可能过少，自顶向下与左兄弟合并，合并不了时从左兄弟借到比最少多一个，
+    // 这样下一层合并时从该节点取走一个键值对后仍不会过少
+    fn fix_right_border(&mut self) {
+        self.fix_top();
+        let (min_pairs_len, max_pairs_len) = (
+            self.max_children_length / 2 - 1,
+            self.max_children_length - 1,
+        );
+        let mut node = match self.root.as_mut() {
+            None => return,
+            Some(root) => Arc::make_mut(root),
+        };
+        while !node.children.is_empty() {
+            let last = node.pairs.len() - 1;
+            let left_len = node.children[last].pairs.len();
+            let right_len = node.children[last + 1].pairs.len();
+            if left_len + 1 + right_len <= max_pairs_len {
+                let separator = node.pairs.pop().unwrap();
+                let right = node.children.pop().unwrap();
+                let left = Arc::make_mut(&mut node.children[last]);
+                merge(separator, left, Arc::unwrap_or_clone(right));
+                node = left;
+            } else {
+                let count = (min_pairs_len + 1).saturating_sub(right_len);
+                let (left, right) = node.children.split_at_mut(last + 1);
+                let right = Arc::make_mut(&mut right[0]);
+                if count > 0 {
+                    bulk_steal_left(
+                        &mut node.pairs[last],
+                        Arc::make_mut(&mut left[last]),
+                        right,
+                        count,
+                    );
+                }
+                node = right;
+            }
+        }
+        self.fix_top();
+    }
+    fn fix_left_border(&mut self) {
+        self.fix_top();
+        let (min_pairs_len, max_pairs_len) = (
+            self.max_children_length / 2 - 1,
+            self.max_children_length - 1,
+        );
+        let mut node = match self.root.as_mut() {
+            None => return,
+            Some(root) => Arc::make_mut(root),
+        };
+        while !node.children.is_empty() {
+            let left_len = node.children[0].pairs.len();
+            let right_len = node.children[1].pairs.len();
+            if left_len + 1 + right_len <= max_pairs_len {
+                let separator = node.pairs.remove(0);
+                let right = node.children.remove(1);
+                let left = Arc::make_mut(&mut node.children[0]);
+                merge(separator, left, Arc::unwrap_or_clone(right));
+                node = left;
+            } else {
+                let count = (min_pairs_len + 1).saturating_sub(left_len);
+                let (left, right) = node.children.split_at_mut(1);
+                let left = Arc::make_mut(&mut left[0]);
+                if count > 0 {
+                    bulk_steal_right(
+                        &mut node.pairs[0],
+                        left,
+                        Arc::make_mut(&mut right[0]),
+                        count,
+                    );
+                }
+                node = left;
+            }
+        }
+        self.fix_top();
+    }
+
+    // 把 other 的键值对全部移入 self，键相同时取 other 的值。两棵树的键区间不重叠时，
+    // 在 O(log n) 时间内把较矮的树接到较高的树上与它等高的位置，否则较小的 other 逐个加入，较大的则归并后重建
+    pub fn append(&mut self, other: &mut Self) {
+        let mut other = std::mem::replace(other, Self::new(other.max_children_length));
+        if self.is_empty() {
+            let max_children_length = self.max_children_length;
+            *self = if other.max_children_length == max_children_length {
+                other
+            } else {
+                // 树的阶不同时按自己的阶重建
+                Self::from_sorted_iter(max_children_length, REBUILD_FILL_FACTOR, other)
+            };
+            return;
+        }
+        let (self_first, _) = self.first_key_value().unwrap();
+        let (self_last, _) = self.last_key_value().unwrap();
+        let joinable = self.max_children_length == other.max_children_length;
+        match (other.first_key_value(), other.last_key_value()) {
+            (None, _) | (_, None) => {}
+            (Some((other_first, _)), _) if joinable && self_last < other_first => {
+                let separator = other.pop_first().unwrap();
+                self.join(separator, other);
+            }
+            (_, Some((other_last, _))) if joinable && other_last < self_first => {
+                let separator = self.pop_first().unwrap();
+                let right = std::mem::replace(self, other);
+                self.join(separator, right);
+            }
+            _ => {
+                // 逐个插入为 O(m log n)，合并重建为 O(n + m)
+                let log_len = (usize::BITS - self.length.leading_zeros()) as usize;
+                if other.length * log_len <= self.length + other.length {
+                    for (key, value) in other {
+                        self.add(key, value);
+                    }
+                } else {
+                    self.extend_sorted(REBUILD_FILL_FACTOR, other);
+                }
+            }
+        }
+    }
+    // 以 separator 连接 self 和 right，self 的键都小于 separator，right 的键都大于 separator，
+    // 较矮的树接在较高的树的边界上与它等高的位置
+    fn join(&mut self, separator: Pair<K, V>, mut right: Self) {
+        let (left_root, right_root) = match (self.root.take(), right.root.take()) {
+            (Some(left_root), Some(right_root)) => (left_root, right_root),
+            (left_root, right_root) => {
+                self.root = left_root.or(right_root);
+                self.length += right.length;
+                self.add(separator.0, separator.1);
+                return;
+            }
+        };
+        self.length += right.length + 1;
+        let (left_height, right_height) = (height(Some(&left_root)), height(Some(&right_root)));
+        self.root = Some(match left_height.cmp(&right_height) {
+            Ordering::Equal => match self.balance_join(left_root, separator, right_root) {
+                (root, None) => root,
+                (left, Some((separator, right))) => Node::new_root(separator, left, right),
+            },
+            Ordering::Greater => {
+                let mut root = left_root;
+                let split =
+                    self.join_right_r(&mut root, left_height, separator, right_root, right_height);
+                match split {
+                    None => root,
+                    Some(split) => Node::new_root(split.mid_pair, root, split.right_child),
+                }
+            }
+            Ordering::Less => {
+                let mut root = right_root;
+                let split =
+                    self.join_left_r(&mut root, right_height, left_root, separator, left_height);
+                match split {
+                    None => root,
+                    Some(split) => Node::new_root(split.mid_pair, root, split.right_child),
+                }
+            }
+        });
+    }
+    // 两棵等高的子树能放进一个节点时合并，否则从较多的一侧借键值对，保证两侧都不少于最少个数
+    #[allow(clippy::type_complexity)]
+    fn balance_join(
+        &self,
+        mut left: Tree<K, V>,
+        mut separator: Pair<K, V>,
+        mut right: Tree<K, V>,
+    ) -> (Tree<K, V>, Option<(Pair<K, V>, Tree<K, V>)>) {
+        let min_pairs_len = self.max_children_length / 2 - 1;
+        let (left_len, right_len) = (left.pairs.len(), right.pairs.len());
+        if left_len + 1 + right_len < self.max_children_length {
+            merge(
+                separator,
+                Arc::make_mut(&mut left),
+                Arc::unwrap_or_clone(right),
+            );
+            return (left, None);
         }
+        if right_len < min_pairs_len {
+            bulk_steal_left(
+                &mut separator,
+                Arc::make_mut(&mut left),
+                Arc::make_mut(&mut right),
+                min_pairs_len - right_len,
+            );
+        } else if left_len < min_pairs_len {
+            bulk_steal_right(
+                &mut separator,
+                Arc::make_mut(&mut left),
+                Arc::make_mut(&mut right),
+                min_pairs_len - left_len,
+            );
+        }
+        (left, Some((separator, right)))
+    }
+    // 沿 node 的最右侧路径下降到子节点与 right 等高处接上 right，返回 node 分裂出的部分
+    fn join_right_r(
+        &mut self,
+        node: &mut Tree<K, V>,
+        node_height: usize,
+        separator: Pair<K, V>,
+        right: Tree<K, V>,
+        right_height: usize,
+    ) -> Option<SplitInfo<K, V>> {
+        let node = Arc::make_mut(node);
+        let (separator, right) = if node_height == right_height + 1 {
+            let left = node.children.pop().unwrap();
+            let (left, rest) = self.balance_join(left, separator, right);
+            node.children.push(left);
+            rest?
+        } else {
+            let last_child = node.children.last_mut().unwrap();
+            let split =
+                self.join_right_r(last_child, node_height - 1, separator, right, right_height)?;
+            (split.mid_pair, split.right_child)
+        };
+        node.add_pair(
+            self.max_children_length,
+            node.pairs.len(),
+            separator,
+            Some(right),
+        )
+    }
+    // 沿 node 的最左侧路径下降到子节点与 left 等高处接上 left，返回 node 分裂出的部分
+    fn join_left_r(
+        &mut self,
+        node: &mut Tree<K, V>,
+        node_height: usize,
+        left: Tree<K, V>,
+        separator: Pair<K, V>,
+        left_height: usize,
+    ) -> Option<SplitInfo<K, V>> {
+        let node = Arc::make_mut(node);
+        let (separator, right) = if node_height == left_height + 1 {
+            let first = node.children.remove(0);
+            let (left, rest) = self.balance_join(left, separator, first);
+            node.children.insert(0, left);
+            rest?
+        } else {
+            let first_child = &mut node.children[0];
+            let split =
+                self.join_left_r(first_child, node_height - 1, left, separator, left_height)?;
+            (split.mid_pair, split.right_child)
+        };
+        // 新的键值对插入到最前面，右侧的子树紧随其后
+        node.add_pair(self.max_children_length, 0, separator, Some(right))
     }
 
     pub fn add(&mut self, key: K, value: V) -> Option<V> {
@@ -183,8 +1071,9 @@ impl<K: Ord, V> BTree<K, V> {
         key: K,
         value: V,
     ) -> (Option<SplitInfo<K, V>>, Option<V>) {
+        let node = Arc::make_mut(node);
         let mut value = value;
-        match node.pairs.binary_search_by_key(&&key, |(key, _value)| &key) {
+        match node.pairs.binary_search_by_key(&&key, |(key, _value)| key) {
             Ok(found) => {
                 std::mem::swap(&mut value, &mut node.pairs[found].1);
                 (None, Some(value))
@@ -215,34 +1104,229 @@ impl<K: Ord, V> BTree<K, V> {
         }
     }
 
-    pub fn remove(&mut self, key: &K) -> Option<V> {
-        let (root, removed_value) = match self.root.take() {
-            None => (None, None),
-            Some(mut root) => {
-                let removed_value = self.remove_r(&mut root, key);
-                if removed_value.is_some() {
-                    if root.pairs.is_empty() {
-                        assert!(root.children.len() <= 1);
-                        (root.children.pop(), removed_value)
-                    } else {
-                        (Some(root), removed_value)
-                    }
-                } else {
-                    (Some(root), None)
-                }
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut node = Arc::make_mut(self.root.as_mut()?);
+        loop {
+            match node.pairs.binary_search_by_key(&key, |(key, _value)| key) {
+                Ok(found) => return Some(&mut node.pairs[found].1),
+                Err(child_index) => node = Arc::make_mut(node.children.get_mut(child_index)?),
             }
-        };
-
-        self.root = root;
-        removed_value
+        }
     }
-    fn remove_r(&mut self, node: &mut Tree<K, V>, key: &K) -> Option<V> {
-        match node.pairs.binary_search_by_key(&key, |(key, _value)| &key) {
-            Ok(found) => {
-                match node.children.get_mut(found) {
-                    None => {
-                        // 未获取到child，则说明node为叶子节点，则执行删除
-                        self.length -= 1;
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let mut root = self.root.take()?;
+        let first = self.remove_left_most_r(&mut root);
+        self.root = self.shrink_root(root);
+        Some(first)
+    }
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let mut root = self.root.take()?;
+        let last = self.remove_right_most_r(&mut root);
+        self.root = self.shrink_root(root);
+        Some(last)
+    }
+    // 根节点的键值对被删空时，由唯一的子节点作为新的根节点
+    fn shrink_root(&self, mut root: Tree<K, V>) -> Option<Tree<K, V>> {
+        if root.pairs.is_empty() {
+            assert!(root.children.len() <= 1);
+            Arc::make_mut(&mut root).children.pop()
+        } else {
+            Some(root)
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let mut path = vec![];
+        let mut node = match self.root.as_ref() {
+            None => {
+                return Entry::Vacant(VacantEntry {
+                    tree: self,
+                    key,
+                    path,
+                    index: 0,
+                })
+            }
+            Some(root) => root,
+        };
+        loop {
+            match node.pairs.binary_search_by_key(&&key, |(key, _value)| key) {
+                Ok(found) => {
+                    return Entry::Occupied(OccupiedEntry {
+                        tree: self,
+                        path,
+                        index: found,
+                    })
+                }
+                Err(to_insert) => match node.children.get(to_insert) {
+                    None => {
+                        return Entry::Vacant(VacantEntry {
+                            tree: self,
+                            key,
+                            path,
+                            index: to_insert,
+                        })
+                    }
+                    Some(child) => {
+                        path.push(to_insert);
+                        node = child;
+                    }
+                },
+            }
+        }
+    }
+    fn node_at_mut(&mut self, path: &[usize]) -> &mut Node<K, V> {
+        let mut node = Arc::make_mut(self.root.as_mut().unwrap());
+        for &child_index in path {
+            node = Arc::make_mut(&mut node.children[child_index]);
+        }
+        node
+    }
+    // 沿 path 插入到叶子节点的 to_insert 处，返回插入后键值对所在的节点和下标
+    fn insert_at_path(
+        &mut self,
+        path: Vec<usize>,
+        to_insert: usize,
+        pair: Pair<K, V>,
+    ) -> (Vec<usize>, usize) {
+        self.length += 1;
+        let mut root = match self.root.take() {
+            None => {
+                self.root = Some(Node::new_with_data(vec![pair], None));
+                return (vec![], 0);
+            }
+            Some(root) => root,
+        };
+        let (split, mut placement) = self.insert_at_path_r(&mut root, &path, to_insert, pair);
+        self.root = match split {
+            None => Some(root),
+            Some(split) => {
+                match placement.side {
+                    Side::Left => placement.path.push(0),
+                    Side::Middle => placement.index = 0,
+                    Side::Right => placement.path.push(1),
+                }
+                Some(Node::new_root(split.mid_pair, root, split.right_child))
+            }
+        };
+        placement.path.reverse();
+        (placement.path, placement.index)
+    }
+    fn insert_at_path_r(
+        &mut self,
+        node: &mut Tree<K, V>,
+        path: &[usize],
+        to_insert: usize,
+        pair: Pair<K, V>,
+    ) -> (Option<SplitInfo<K, V>>, Placement) {
+        let node = Arc::make_mut(node);
+        let pairs_len = node.pairs.len();
+        let (child_index, rest) = match path.split_first() {
+            None => {
+                let (side, index) = place_pair(pairs_len, self.max_children_length, to_insert);
+                let split = node.add_pair(self.max_children_length, to_insert, pair, None);
+                let placement = Placement {
+                    side,
+                    path: vec![],
+                    index,
+                };
+                return (split, placement);
+            }
+            Some((&child_index, rest)) => (child_index, rest),
+        };
+
+        let child = &mut node.children[child_index];
+        let (split, child_placement) = self.insert_at_path_r(child, rest, to_insert, pair);
+        let split = match split {
+            None => {
+                let mut placement = child_placement;
+                placement.path.push(child_index);
+                return (None, placement);
+            }
+            Some(split) => split,
+        };
+        // 子节点分裂后，中间键值对插入到 child_index 处，右半部分成为 child_index + 1 处的子节点
+        let placement = match child_placement.side {
+            Side::Middle => {
+                let (side, index) = place_pair(pairs_len, self.max_children_length, child_index);
+                Placement {
+                    side,
+                    path: vec![],
+                    index,
+                }
+            }
+            Side::Left | Side::Right => {
+                let combined_index = match child_placement.side {
+                    Side::Left => child_index,
+                    _ => child_index + 1,
+                };
+                let (side, child_index) =
+                    place_child(pairs_len, self.max_children_length, combined_index);
+                let mut path = child_placement.path;
+                path.push(child_index);
+                Placement {
+                    side,
+                    path,
+                    index: child_placement.index,
+                }
+            }
+        };
+        let split = node.add_pair(
+            self.max_children_length,
+            child_index,
+            split.mid_pair,
+            Some(split.right_child),
+        );
+        (split, placement)
+    }
+    // 删除 path 所指节点中下标为 index 的键值对
+    fn remove_at_path(&mut self, path: &[usize], index: usize) -> Pair<K, V> {
+        let mut root = self.root.take().unwrap();
+        let removed = self.remove_at_path_r(&mut root, path, index);
+        self.root = self.shrink_root(root);
+        removed
+    }
+    fn remove_at_path_r(
+        &mut self,
+        node: &mut Tree<K, V>,
+        path: &[usize],
+        index: usize,
+    ) -> Pair<K, V> {
+        let node = Arc::make_mut(node);
+        match path.split_first() {
+            None => match node.children.get_mut(index) {
+                None => {
+                    self.length -= 1;
+                    node.pairs.remove(index)
+                }
+                Some(child) => {
+                    let mut pre = self.remove_right_most_r(child);
+                    std::mem::swap(&mut pre, &mut node.pairs[index]);
+                    self.fix_on_child_removed(node, index);
+                    pre
+                }
+            },
+            Some((&child_index, rest)) => {
+                let removed = self.remove_at_path_r(&mut node.children[child_index], rest, index);
+                self.fix_on_child_removed(node, child_index);
+                removed
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let mut root = self.root.take()?;
+        let removed_value = self.remove_r(&mut root, key);
+        self.root = self.shrink_root(root);
+        removed_value
+    }
+    fn remove_r(&mut self, node: &mut Tree<K, V>, key: &K) -> Option<V> {
+        let node = Arc::make_mut(node);
+        match node.pairs.binary_search_by_key(&key, |(key, _value)| key) {
+            Ok(found) => {
+                match node.children.get_mut(found) {
+                    None => {
+                        // 未获取到child，则说明node为叶子节点，则执行删除
+                        self.length -= 1;
                         Some(node.pairs.remove(found).1)
                     }
                     Some(child) => {
@@ -263,7 +1347,23 @@ impl<K: Ord, V> BTree<K, V> {
             },
         }
     }
+    fn remove_left_most_r(&mut self, node: &mut Tree<K, V>) -> Pair<K, V> {
+        let node = Arc::make_mut(node);
+        match node.children.first_mut() {
+            None => {
+                // 未获取到child，则说明node为叶子节点，则执行删除
+                self.length -= 1;
+                node.pairs.remove(0)
+            }
+            Some(first_child) => {
+                let removed = self.remove_left_most_r(first_child);
+                self.fix_on_child_removed(node, 0);
+                removed
+            }
+        }
+    }
     fn remove_right_most_r(&mut self, node: &mut Tree<K, V>) -> Pair<K, V> {
+        let node = Arc::make_mut(node);
         match node.children.last_mut() {
             None => {
                 // 未获取到child，则说明node为叶子节点，则执行删除
@@ -277,151 +1377,671 @@ impl<K: Ord, V> BTree<K, V> {
             }
         }
     }
-    fn fix_on_child_removed(&mut self, node: &mut Tree<K, V>, child_index: usize) {
-        let min_pairs_len = self.max_children_length / 2 - 1;
-        if node.children[child_index].pairs.len() >= min_pairs_len {
-            return;
+    fn fix_on_child_removed(&mut self, node: &mut Node<K, V>, child_index: usize) {
+        let pairs_len = |index: usize| node.children.get(index).map(|child| child.pairs.len());
+        let rebalance = plan_rebalance(
+            self.max_children_length / 2 - 1,
+            child_index,
+            node.children[child_index].pairs.len(),
+            child_index.checked_sub(1).and_then(pairs_len),
+            pairs_len(child_index + 1),
+        );
+        match rebalance {
+            Rebalance::None => {}
+            Rebalance::BorrowLeft => {
+                let (left, right) = node.children.split_at_mut(child_index);
+                borrow_from_left(
+                    &mut node.pairs[child_index - 1],
+                    Arc::make_mut(&mut left[child_index - 1]),
+                    Arc::make_mut(&mut right[0]),
+                );
+            }
+            Rebalance::BorrowRight => {
+                let (left, right) = node.children.split_at_mut(child_index + 1);
+                borrow_from_right(
+                    &mut node.pairs[child_index],
+                    Arc::make_mut(&mut left[child_index]),
+                    Arc::make_mut(&mut right[0]),
+                );
+            }
+            Rebalance::Merge(left_index) => {
+                let separator = node.pairs.remove(left_index);
+                let right = node.children.remove(left_index + 1);
+                merge(
+                    separator,
+                    Arc::make_mut(&mut node.children[left_index]),
+                    Arc::unwrap_or_clone(right),
+                );
+            }
         }
+    }
 
-        let left_child_index = match child_index.checked_sub(1) {
-            None => None,
-            Some(left_child_index) => {
-                if node.children[left_child_index].pairs.len() > min_pairs_len {
-                    let mut temp = node.children[left_child_index].pairs.pop().unwrap();
-                    std::mem::swap(&mut temp, &mut node.pairs[left_child_index]);
-                    node.children[child_index].pairs.insert(0, temp);
-                    if let Some(left_child_right) = node.children[left_child_index].children.pop() {
-                        node.children[child_index]
-                            .children
-                            .insert(0, left_child_right);
-                    }
-                    return;
-                }
-                Some(left_child_index)
-            }
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            traversal: Traversal::new(self.root.as_mut()),
+            remain_len: self.length,
+        }
+    }
+}
+
+// 下界在节点中的位置：第一个在范围内的键值对下标 start，及下标为 start 的子节点是否只有部分在范围内
+fn lower_edge<K: Ord, V>(node: &Node<K, V>, bound: Bound<&K>) -> (usize, Option<bool>) {
+    let search = |key| node.pairs.binary_search_by_key(&key, |(key, _value)| key);
+    match bound {
+        Bound::Included(key) => match search(key) {
+            Ok(found) => (found, None),
+            Err(child_index) => (child_index, Some(true)),
+        },
+        Bound::Excluded(key) => match search(key) {
+            Ok(found) => (found + 1, Some(false)),
+            Err(child_index) => (child_index, Some(true)),
+        },
+        Bound::Unbounded => (0, Some(false)),
+    }
+}
+
+// 上界在节点中的位置：在范围内的键值对个数 end，及下标为 end 的子节点是否只有部分在范围内
+fn upper_edge<K: Ord, V>(node: &Node<K, V>, bound: Bound<&K>) -> (usize, Option<bool>) {
+    let search = |key| node.pairs.binary_search_by_key(&key, |(key, _value)| key);
+    match bound {
+        Bound::Included(key) => match search(key) {
+            Ok(found) => (found + 1, None),
+            Err(child_index) => (child_index, Some(true)),
+        },
+        Bound::Excluded(key) => match search(key) {
+            Ok(found) => (found, Some(false)),
+            Err(child_index) => (child_index, Some(true)),
+        },
+        Bound::Unbounded => (node.pairs.len(), Some(false)),
+    }
+}
+
+// 对子树的引用或所有权，展开后得到节点内的键值对与子节点
+trait Subtree: Sized {
+    type Pairs: DoubleEndedIterator;
+    type Children: DoubleEndedIterator<Item = Self>;
+
+    fn open(self) -> (Self::Pairs, Self::Children);
+}
+
+impl<'t, K: Ord, V> Subtree for &'t Tree<K, V> {
+    type Pairs = std::slice::Iter<'t, Pair<K, V>>;
+    type Children = std::slice::Iter<'t, Tree<K, V>>;
+
+    fn open(self) -> (Self::Pairs, Self::Children) {
+        (self.pairs.iter(), self.children.iter())
+    }
+}
+
+impl<'t, K: Ord + Clone, V: Clone> Subtree for &'t mut Tree<K, V> {
+    type Pairs = std::slice::IterMut<'t, Pair<K, V>>;
+    type Children = std::slice::IterMut<'t, Tree<K, V>>;
+
+    fn open(self) -> (Self::Pairs, Self::Children) {
+        let Node { pairs, children } = Arc::make_mut(self);
+        (pairs.iter_mut(), children.iter_mut())
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Subtree for Tree<K, V> {
+    type Pairs = std::vec::IntoIter<Pair<K, V>>;
+    type Children = std::vec::IntoIter<Tree<K, V>>;
+
+    fn open(self) -> (Self::Pairs, Self::Children) {
+        let Node { pairs, children } = Arc::unwrap_or_clone(self);
+        (pairs.into_iter(), children.into_iter())
+    }
+}
+
+// 一个节点内尚未遍历的部分，键值对与子节点交替出现
+struct Level<T: Subtree> {
+    pairs: T::Pairs,
+    children: T::Children,
+}
+
+// 从两端同时进行的中序遍历，front 和 back 各自独占的节点以栈保存，两端共同所在的节点为 shared
+struct Traversal<T: Subtree> {
+    front: Vec<Level<T>>,
+    shared: Option<Level<T>>,
+    back: Vec<Level<T>>,
+}
+
+impl<T: Subtree> Traversal<T> {
+    fn new(root: Option<T>) -> Self {
+        let mut traversal = Self {
+            front: vec![],
+            shared: None,
+            back: vec![],
         };
-        let right_child_index = child_index + 1;
-        let right_child_index = match right_child_index >= node.children.len() {
-            true => None,
-            false => {
-                if node.children[right_child_index].pairs.len() > min_pairs_len {
-                    let mut temp = node.children[right_child_index].pairs.remove(0);
-                    std::mem::swap(&mut temp, &mut node.pairs[child_index]);
-                    node.children[child_index].pairs.push(temp);
-                    if !node.children[right_child_index].children.is_empty() {
-                        let right_child_left = node.children[right_child_index].children.remove(0);
-                        node.children[child_index].children.push(right_child_left);
-                    }
-                    return;
+        if let Some(root) = root {
+            let (pairs, mut children) = root.open();
+            if let Some(first) = children.next() {
+                Self::push_left(&mut traversal.front, first);
+            }
+            if let Some(last) = children.next_back() {
+                Self::push_right(&mut traversal.back, last);
+            }
+            traversal.shared = Some(Level { pairs, children });
+        }
+        traversal
+    }
+    fn push_left(stack: &mut Vec<Level<T>>, mut tree: T) {
+        loop {
+            let (pairs, mut children) = tree.open();
+            let first = children.next();
+            stack.push(Level { pairs, children });
+            match first {
+                None => return,
+                Some(first) => tree = first,
+            }
+        }
+    }
+    fn push_right(stack: &mut Vec<Level<T>>, mut tree: T) {
+        loop {
+            let (pairs, mut children) = tree.open();
+            let last = children.next_back();
+            stack.push(Level { pairs, children });
+            match last {
+                None => return,
+                Some(last) => tree = last,
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<<T::Pairs as Iterator>::Item> {
+        loop {
+            let level = match self.front.last_mut() {
+                Some(level) => level,
+                None => self.shared.as_mut()?,
+            };
+            if let Some(pair) = level.pairs.next() {
+                if let Some(child) = level.children.next() {
+                    Self::push_left(&mut self.front, child);
                 }
-                Some(right_child_index)
+                return Some(pair);
             }
-        };
-        let (merge_left, to_merge_child_index) = match (left_child_index, right_child_index) {
-            (Some(left_child_index), Some(right_child_index)) => {
-                if node.children[left_child_index].pairs.len()
-                    < node.children[right_child_index].pairs.len()
-                {
-                    (true, left_child_index)
-                } else {
-                    (false, right_child_index)
+            if self.front.pop().is_some() {
+                continue;
+            }
+            // 共同所在的节点已遍历完，接管 back 栈底的节点
+            if self.back.is_empty() {
+                self.shared = None;
+                return None;
+            }
+            let mut level = self.back.remove(0);
+            if let Some(child) = level.children.next() {
+                Self::push_left(&mut self.front, child);
+            }
+            self.shared = Some(level);
+        }
+    }
+    fn next_back(&mut self) -> Option<<T::Pairs as Iterator>::Item> {
+        loop {
+            let level = match self.back.last_mut() {
+                Some(level) => level,
+                None => self.shared.as_mut()?,
+            };
+            if let Some(pair) = level.pairs.next_back() {
+                if let Some(child) = level.children.next_back() {
+                    Self::push_right(&mut self.back, child);
                 }
+                return Some(pair);
             }
-            (None, Some(right_child_index)) => (false, right_child_index),
-            (Some(left_child_index), None) => (true, left_child_index),
-            (None, None) => {
-                unreachable!()
+            if self.back.pop().is_some() {
+                continue;
             }
-        };
-
-        let (merge_left, pair_in_node, mut merge_right) = if merge_left {
-            let pair_in_node = node.pairs.remove(to_merge_child_index);
-            let child = node.children.remove(child_index);
-            let left_child = &mut node.children[to_merge_child_index];
-            (left_child, pair_in_node, child)
-        } else {
-            let pair_in_node = node.pairs.remove(child_index);
-            let merge_right = node.children.remove(to_merge_child_index);
-            let merge_left = &mut node.children[child_index];
-            (merge_left, pair_in_node, merge_right)
-        };
-        merge_left.pairs.push(pair_in_node);
-        merge_left.pairs.append(&mut merge_right.pairs);
-        merge_left.children.append(&mut merge_right.children);
+            if self.front.is_empty() {
+                self.shared = None;
+                return None;
+            }
+            let mut level = self.front.remove(0);
+            if let Some(child) = level.children.next_back() {
+                Self::push_right(&mut self.back, child);
+            }
+            self.shared = Some(level);
+        }
     }
+}
 
-    pub fn iter(&self) -> Iter<K, V> {
-        Iter {
-            b_tree: self,
-            remain_len: self.len(),
-            stack: vec![],
+impl<'t, K: Ord, V> Traversal<&'t Tree<K, V>> {
+    // 找到两个边界分开的节点作为 shared，再分别下降到两个边界
+    fn seek(&mut self, root: &'t Tree<K, V>, lower: Bound<&K>, upper: Bound<&K>) {
+        let mut node = root;
+        loop {
+            let (start, front_child) = lower_edge(node, lower);
+            let (end, back_child) = upper_edge(node, upper);
+            if node.children.is_empty() {
+                self.shared = Some(Level {
+                    pairs: node.pairs[start..end].iter(),
+                    children: [].iter(),
+                });
+                return;
+            }
+            if front_child.is_some() && back_child.is_some() && start == end {
+                node = &node.children[start];
+                continue;
+            }
+
+            self.shared = Some(Level {
+                pairs: node.pairs[start..end].iter(),
+                children: node.children.get(start + 1..end).unwrap_or(&[]).iter(),
+            });
+            match front_child {
+                Some(true) => self.seek_front(&node.children[start], lower),
+                Some(false) => Self::push_left(&mut self.front, &node.children[start]),
+                None => {}
+            }
+            match back_child {
+                Some(true) => self.seek_back(&node.children[end], upper),
+                Some(false) => Self::push_right(&mut self.back, &node.children[end]),
+                None => {}
+            }
+            return;
+        }
+    }
+    fn seek_front(&mut self, mut node: &'t Tree<K, V>, lower: Bound<&K>) {
+        loop {
+            let (start, child) = lower_edge(node, lower);
+            self.front.push(Level {
+                pairs: node.pairs[start..].iter(),
+                children: node.children.get(start + 1..).unwrap_or(&[]).iter(),
+            });
+            if node.children.is_empty() {
+                return;
+            }
+            match child {
+                Some(true) => node = &node.children[start],
+                Some(false) => return Self::push_left(&mut self.front, &node.children[start]),
+                None => return,
+            }
+        }
+    }
+    fn seek_back(&mut self, mut node: &'t Tree<K, V>, upper: Bound<&K>) {
+        loop {
+            let (end, child) = upper_edge(node, upper);
+            self.back.push(Level {
+                pairs: node.pairs[..end].iter(),
+                children: node.children.get(..end).unwrap_or(&[]).iter(),
+            });
+            if node.children.is_empty() {
+                return;
+            }
+            match child {
+                Some(true) => node = &node.children[end],
+                Some(false) => return Self::push_right(&mut self.back, &node.children[end]),
+                None => return,
+            }
         }
     }
 }
 
 pub struct Iter<'t, K: Ord, V> {
-    b_tree: &'t BTree<K, V>,
+    traversal: Traversal<&'t Tree<K, V>>,
+    remain_len: usize,
+}
+
+impl<'t, K: Ord, V> Iterator for Iter<'t, K, V> {
+    type Item = &'t Pair<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pair = self.traversal.next()?;
+        self.remain_len -= 1;
+        Some(pair)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+impl<'t, K: Ord, V> DoubleEndedIterator for Iter<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let pair = self.traversal.next_back()?;
+        self.remain_len -= 1;
+        Some(pair)
+    }
+}
+
+impl<'t, K: Ord, V> IntoIterator for &'t BTree<K, V> {
+    type Item = &'t Pair<K, V>;
+    type IntoIter = Iter<'t, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct IterMut<'t, K: Ord + Clone, V: Clone> {
+    traversal: Traversal<&'t mut Tree<K, V>>,
+    remain_len: usize,
+}
+
+impl<'t, K: Ord + Clone, V: Clone> Iterator for IterMut<'t, K, V> {
+    type Item = (&'t K, &'t mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.traversal.next()?;
+        self.remain_len -= 1;
+        Some((key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+impl<'t, K: Ord + Clone, V: Clone> DoubleEndedIterator for IterMut<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.traversal.next_back()?;
+        self.remain_len -= 1;
+        Some((key, value))
+    }
+}
+
+impl<'t, K: Ord + Clone, V: Clone> IntoIterator for &'t mut BTree<K, V> {
+    type Item = (&'t K, &'t mut V);
+    type IntoIter = IterMut<'t, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct IntoIter<K: Ord + Clone, V: Clone> {
+    traversal: Traversal<Tree<K, V>>,
     remain_len: usize,
-    stack: Vec<(&'t Tree<K, V>, usize)>,
 }
 
-impl<'t, K: Ord, V> Iter<'t, K, V> {
-    fn push_node(&mut self, node: &'t Tree<K, V>) {
-        self.stack.push((node, 0));
-        let mut left = node;
-        while let Some(left_left) = left.children.first() {
-            left = left_left;
-            self.stack.push((left, 0));
+impl<K: Ord + Clone, V: Clone> Iterator for IntoIter<K, V> {
+    type Item = Pair<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pair = self.traversal.next()?;
+        self.remain_len -= 1;
+        Some(pair)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let pair = self.traversal.next_back()?;
+        self.remain_len -= 1;
+        Some(pair)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> IntoIterator for BTree<K, V> {
+    type Item = Pair<K, V>;
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            traversal: Traversal::new(self.root),
+            remain_len: self.length,
         }
     }
-    fn pop_node(&mut self) {
-        self.stack.pop();
-        while let Some((top_node, pair_index_in_top_node)) = self.stack.last() {
-            if *pair_index_in_top_node < top_node.pairs.len() {
-                return;
-            } else {
-                self.stack.pop();
+}
+
+pub struct Range<'t, K: Ord, V> {
+    traversal: Traversal<&'t Tree<K, V>>,
+}
+
+impl<'t, K: Ord, V> Iterator for Range<'t, K, V> {
+    type Item = &'t Pair<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.traversal.next()
+    }
+}
+
+impl<'t, K: Ord, V> DoubleEndedIterator for Range<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.traversal.next_back()
+    }
+}
+
+pub struct Keys<'t, K: Ord, V> {
+    iter: Iter<'t, K, V>,
+}
+
+impl<'t, K: Ord, V> Iterator for Keys<'t, K, V> {
+    type Item = &'t K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, _value)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'t, K: Ord, V> DoubleEndedIterator for Keys<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(key, _value)| key)
+    }
+}
+
+pub struct Values<'t, K: Ord, V> {
+    iter: Iter<'t, K, V>,
+}
+
+impl<'t, K: Ord, V> Iterator for Values<'t, K, V> {
+    type Item = &'t V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(_key, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'t, K: Ord, V> DoubleEndedIterator for Values<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(_key, value)| value)
+    }
+}
+
+// 同时按键的顺序遍历两棵树，每次返回最小的键在两棵树中各自对应的键值对
+struct MergeIter<'t, K: Ord, V> {
+    left: Peekable<Iter<'t, K, V>>,
+    right: Peekable<Iter<'t, K, V>>,
+}
+
+impl<'t, K: Ord, V> MergeIter<'t, K, V> {
+    fn new(left: &'t BTree<K, V>, right: &'t BTree<K, V>) -> Self {
+        Self {
+            left: left.iter().peekable(),
+            right: right.iter().peekable(),
+        }
+    }
+}
+
+impl<'t, K: Ord, V> Iterator for MergeIter<'t, K, V> {
+    type Item = (Option<&'t Pair<K, V>>, Option<&'t Pair<K, V>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ordering = match (self.left.peek(), self.right.peek()) {
+            (None, None) => return None,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(left), Some(right)) => left.0.cmp(&right.0),
+        };
+        Some(match ordering {
+            Ordering::Less => (self.left.next(), None),
+            Ordering::Equal => (self.left.next(), self.right.next()),
+            Ordering::Greater => (None, self.right.next()),
+        })
+    }
+}
+
+pub struct Union<'t, K: Ord, V> {
+    merge: MergeIter<'t, K, V>,
+}
+
+impl<'t, K: Ord, V> Iterator for Union<'t, K, V> {
+    type Item = &'t Pair<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (left, right) = self.merge.next()?;
+        left.or(right)
+    }
+}
+
+pub struct Intersection<'t, K: Ord, V> {
+    merge: MergeIter<'t, K, V>,
+}
+
+impl<'t, K: Ord, V> Iterator for Intersection<'t, K, V> {
+    type Item = &'t Pair<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let (Some(left), Some(_)) = self.merge.next()? {
+                return Some(left);
             }
         }
     }
 }
 
-impl<'t, K: Ord, V> Iterator for Iter<'t, K, V> {
+pub struct Difference<'t, K: Ord, V> {
+    merge: MergeIter<'t, K, V>,
+}
+
+impl<'t, K: Ord, V> Iterator for Difference<'t, K, V> {
     type Item = &'t Pair<K, V>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.remain_len == 0 {
-            return None;
+        loop {
+            if let (Some(left), None) = self.merge.next()? {
+                return Some(left);
+            }
         }
+    }
+}
+
+pub struct SymmetricDifference<'t, K: Ord, V> {
+    merge: MergeIter<'t, K, V>,
+}
+
+impl<'t, K: Ord, V> Iterator for SymmetricDifference<'t, K, V> {
+    type Item = &'t Pair<K, V>;
 
-        if self.stack.is_empty() {
-            let root = self.b_tree.root.as_ref().unwrap();
-            self.push_node(root);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.merge.next()? {
+                (Some(pair), None) | (None, Some(pair)) => return Some(pair),
+                _ => {}
+            }
         }
+    }
+}
 
-        let (top_node, pair_index_in_top_node) = self.stack.last_mut().unwrap();
-        let res = &top_node.pairs[*pair_index_in_top_node];
-        *pair_index_in_top_node += 1;
-        if let Some(next_child) = top_node.children.get(*pair_index_in_top_node) {
-            self.push_node(next_child)
-        } else if *pair_index_in_top_node >= top_node.pairs.len() {
-            self.pop_node();
+pub enum Entry<'t, K: Ord, V> {
+    Occupied(OccupiedEntry<'t, K, V>),
+    Vacant(VacantEntry<'t, K, V>),
+}
+
+// path 为从根节点到键值对所在节点的子节点下标
+pub struct OccupiedEntry<'t, K: Ord, V> {
+    tree: &'t mut BTree<K, V>,
+    path: Vec<usize>,
+    index: usize,
+}
+
+// path 和 index 为查找停下的叶子节点及插入位置
+pub struct VacantEntry<'t, K: Ord, V> {
+    tree: &'t mut BTree<K, V>,
+    key: K,
+    path: Vec<usize>,
+    index: usize,
+}
+
+impl<'t, K: Ord + Clone, V: Clone> Entry<'t, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+    pub fn or_insert(self, default: V) -> &'t mut V {
+        self.or_insert_with(|| default)
+    }
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'t mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
         }
+        self
+    }
+}
 
-        self.remain_len -= 1;
-        Some(res)
+impl<'t, K: Ord + Clone, V: Clone + Default> Entry<'t, K, V> {
+    pub fn or_default(self) -> &'t mut V {
+        self.or_insert_with(V::default)
     }
+}
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.remain_len, Some(self.remain_len))
+impl<'t, K: Ord + Clone, V: Clone> OccupiedEntry<'t, K, V> {
+    pub fn key(&self) -> &K {
+        &self.tree.node_at(&self.path).pairs[self.index].0
+    }
+    pub fn get(&self) -> &V {
+        &self.tree.node_at(&self.path).pairs[self.index].1
+    }
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.tree.node_at_mut(&self.path).pairs[self.index].1
+    }
+    pub fn into_mut(self) -> &'t mut V {
+        &mut self.tree.node_at_mut(&self.path).pairs[self.index].1
+    }
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+    pub fn remove_entry(self) -> Pair<K, V> {
+        self.tree.remove_at_path(&self.path, self.index)
+    }
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+impl<'t, K: Ord + Clone, V: Clone> VacantEntry<'t, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+    pub fn into_key(self) -> K {
+        self.key
+    }
+    pub fn insert(self, value: V) -> &'t mut V {
+        let (path, index) = self
+            .tree
+            .insert_at_path(self.path, self.index, (self.key, value));
+        &mut self.tree.node_at_mut(&path).pairs[index].1
     }
 }
 
 #[cfg(test)]
 mod tests {
     use rand::prelude::SliceRandom;
+    use rand::Rng;
+
+    use std::ops::Bound;
+    use std::sync::Arc;
 
-    use crate::b_tree::BTree;
+    use crate::b_tree::{BTree, Entry, Node};
+    use crate::invariant::InvariantViolation;
 
     #[test]
     fn add_and_remove() {
@@ -455,6 +2075,153 @@ mod tests {
         assert_eq!(b_tree.len(), 0);
     }
 
+    #[test]
+    fn validate() {
+        let mut b_tree = BTree::new(4);
+        for i in 0..20 {
+            b_tree.add(i, i);
+        }
+        assert_eq!(b_tree.validate(), Ok(()));
+
+        let key = &mut b_tree.node_at_mut(&[1]).pairs[0].0;
+        let old_key = std::mem::replace(key, 100);
+        assert_eq!(
+            b_tree.validate(),
+            Err(InvariantViolation::KeyOrder { path: vec![1] })
+        );
+        b_tree.node_at_mut(&[1]).pairs[0].0 = old_key;
+        assert_eq!(b_tree.validate(), Ok(()));
+
+        let leaf = |key| Node::new_with_data(vec![(key, key)], None);
+        let left = Node::new_with_data(vec![(5, 5)], Some(vec![leaf(1), leaf(7)]));
+        b_tree.root = Some(Node::new_with_data(
+            vec![(10, 10)],
+            Some(vec![left, leaf(20)]),
+        ));
+        b_tree.length = 5;
+        assert_eq!(
+            b_tree.validate(),
+            Err(InvariantViolation::LeafDepth {
+                path: vec![1],
+                depth: 1,
+                expected: 2
+            })
+        );
+
+        let mut b_tree = BTree::new(4);
+        for i in 0..3 {
+            b_tree.add(i, i);
+        }
+        b_tree.node_at_mut(&[]).pairs.push((10, 10));
+        assert_eq!(
+            b_tree.validate(),
+            Err(InvariantViolation::FanOut {
+                path: vec![],
+                children: 5,
+                min: 2,
+                max: 4
+            })
+        );
+        b_tree.node_at_mut(&[]).pairs.pop();
+        b_tree.length = 4;
+        assert_eq!(
+            b_tree.validate(),
+            Err(InvariantViolation::Length {
+                recorded: 4,
+                counted: 3
+            })
+        );
+    }
+
+    #[test]
+    fn lookup() {
+        let mut rng = rand::thread_rng();
+        let mut b_tree = BTree::new(6);
+        let mut std_tree = std::collections::BTreeMap::new();
+        assert_eq!(b_tree.first_key_value(), None);
+        assert_eq!(b_tree.pop_last(), None);
+        for _ in 0..20_000 {
+            let key = rng.gen_range(0..1000);
+            match rng.gen_range(0..10) {
+                0 => assert_eq!(b_tree.pop_first(), std_tree.pop_first()),
+                1 => assert_eq!(b_tree.pop_last(), std_tree.pop_last()),
+                2 => {
+                    if let Some(value) = b_tree.get_mut(&key) {
+                        *value += 1;
+                    }
+                    if let Some(value) = std_tree.get_mut(&key) {
+                        *value += 1;
+                    }
+                }
+                _ => assert_eq!(b_tree.add(key, key), std_tree.insert(key, key)),
+            }
+            assert_eq!(b_tree.get(&key), std_tree.get(&key));
+            assert_eq!(b_tree.contains_key(&key), std_tree.contains_key(&key));
+            assert_eq!(b_tree.first_key_value(), std_tree.first_key_value());
+            assert_eq!(b_tree.last_key_value(), std_tree.last_key_value());
+            assert_eq!(b_tree.len(), std_tree.len());
+        }
+        assert!(b_tree.is_valid());
+        while let Some(first) = std_tree.pop_first() {
+            assert_eq!(b_tree.pop_first(), Some(first));
+        }
+        assert!(b_tree.is_empty());
+        assert!(b_tree.root.is_none());
+    }
+
+    #[test]
+    fn entry() {
+        let mut rng = rand::thread_rng();
+        for &max_children_length in &[4, 5, 8] {
+            let mut b_tree = BTree::new(max_children_length);
+            let mut std_tree = std::collections::BTreeMap::new();
+            for _ in 0..20_000 {
+                let key = rng.gen_range(0..2000);
+                if rng.gen_range(0..4) == 0 {
+                    let removed = match b_tree.entry(key) {
+                        Entry::Occupied(entry) => {
+                            assert_eq!(entry.key(), &key);
+                            Some(entry.remove_entry())
+                        }
+                        Entry::Vacant(entry) => {
+                            assert_eq!(entry.into_key(), key);
+                            None
+                        }
+                    };
+                    assert_eq!(removed, std_tree.remove_entry(&key));
+                } else {
+                    let count = b_tree
+                        .entry(key)
+                        .and_modify(|count| *count += 1)
+                        .or_insert(1);
+                    let std_count = std_tree
+                        .entry(key)
+                        .and_modify(|count| *count += 1)
+                        .or_insert(1);
+                    assert_eq!(count, std_count);
+                    *count *= 2;
+                    *std_count *= 2;
+                }
+                assert_eq!(b_tree.len(), std_tree.len());
+            }
+            assert!(b_tree.is_valid());
+            assert!(b_tree.iter().map(|(k, v)| (k, v)).eq(std_tree.iter()));
+        }
+
+        let mut b_tree = BTree::default();
+        *b_tree.entry("a").or_insert_with(|| 10) += 1;
+        *b_tree.entry("a").or_default() += 1;
+        *b_tree.entry("b").or_default() += 1;
+        assert_eq!(b_tree.get(&"a"), Some(&12));
+        assert_eq!(b_tree.get(&"b"), Some(&1));
+        if let Entry::Occupied(mut entry) = b_tree.entry("b") {
+            assert_eq!(entry.insert(5), 1);
+            assert_eq!(entry.get(), &5);
+            assert_eq!(entry.remove(), 5);
+        }
+        assert_eq!(b_tree.len(), 1);
+    }
+
     #[test]
     fn iter() {
         let test_nums = 100_000usize;
@@ -475,6 +2242,120 @@ mod tests {
         assert_eq!(num, b_tree.len());
     }
 
+    #[test]
+    fn iter_both_ends() {
+        let mut rng = rand::thread_rng();
+        for &max_children_length in &[4, 5, 16] {
+            let mut b_tree = BTree::new(max_children_length);
+            let mut std_tree = std::collections::BTreeMap::new();
+            for _ in 0..3000 {
+                let key = rng.gen_range(0..5000);
+                b_tree.add(key, key);
+                std_tree.insert(key, key);
+            }
+
+            let pairs: Vec<(i32, i32)> = std_tree.iter().map(|(&k, &v)| (k, v)).collect();
+            assert!(b_tree.iter().copied().eq(pairs.iter().copied()));
+            assert!(b_tree.iter().rev().copied().eq(pairs.iter().rev().copied()));
+            assert!(b_tree.keys().eq(std_tree.keys()));
+            assert!(b_tree.values().rev().eq(std_tree.values().rev()));
+
+            // 两端交替前进，直到在中间相遇
+            let mut iter = b_tree.iter();
+            let mut std_iter = std_tree.iter();
+            loop {
+                let pair = if rng.gen_bool(0.5) {
+                    (iter.next(), std_iter.next())
+                } else {
+                    (iter.next_back(), std_iter.next_back())
+                };
+                assert_eq!(pair.0.map(|(k, v)| (k, v)), pair.1);
+                assert_eq!(iter.size_hint(), std_iter.size_hint());
+                if pair.1.is_none() {
+                    break;
+                }
+            }
+
+            b_tree.iter_mut().for_each(|(_key, value)| *value *= 2);
+            std_tree.iter_mut().for_each(|(_key, value)| *value *= 2);
+            let mut iter_mut = b_tree.iter_mut();
+            while let Some((_key, value)) = iter_mut.next_back() {
+                *value += 1;
+                if let Some((_key, value)) = iter_mut.next() {
+                    *value += 1;
+                }
+            }
+            std_tree.values_mut().for_each(|value| *value += 1);
+            assert!(b_tree.iter().map(|(k, v)| (k, v)).eq(std_tree.iter()));
+
+            let mut into_iter = b_tree.into_iter();
+            let mut std_into_iter = std_tree.into_iter();
+            assert_eq!(into_iter.next_back(), std_into_iter.next_back());
+            assert!(into_iter.eq(std_into_iter));
+        }
+    }
+
+    fn random_bound(rng: &mut impl Rng) -> Bound<i32> {
+        match rng.gen_range(0..3) {
+            0 => Bound::Included(rng.gen_range(-10..2010)),
+            1 => Bound::Excluded(rng.gen_range(-10..2010)),
+            _ => Bound::Unbounded,
+        }
+    }
+
+    #[test]
+    fn range() {
+        let mut rng = rand::thread_rng();
+        for &max_children_length in &[4, 7, 32] {
+            let mut b_tree = BTree::new(max_children_length);
+            let mut std_tree = std::collections::BTreeMap::new();
+            for _ in 0..2000 {
+                let key = rng.gen_range(0..1000) * 2;
+                b_tree.add(key, -key);
+                std_tree.insert(key, -key);
+            }
+
+            for _ in 0..2000 {
+                let bounds = (random_bound(&mut rng), random_bound(&mut rng));
+                // std 在这些情况下会 panic，这里返回空的范围
+                let std_panics = match bounds {
+                    (
+                        Bound::Included(start) | Bound::Excluded(start),
+                        Bound::Included(end) | Bound::Excluded(end),
+                    ) if start > end => true,
+                    (Bound::Excluded(start), Bound::Excluded(end)) => start == end,
+                    _ => false,
+                };
+                if std_panics {
+                    assert_eq!(b_tree.range(bounds).count(), 0);
+                    continue;
+                }
+                let expected: Vec<_> = std_tree.range(bounds).map(|(&k, &v)| (k, v)).collect();
+                assert!(b_tree.range(bounds).copied().eq(expected.iter().copied()));
+                assert!(b_tree
+                    .range(bounds)
+                    .rev()
+                    .copied()
+                    .eq(expected.iter().rev().copied()));
+
+                let mut range = b_tree.range(bounds);
+                let (mut front, mut back) = (0, expected.len());
+                while front < back {
+                    if rng.gen_bool(0.5) {
+                        assert_eq!(range.next(), Some(&expected[front]));
+                        front += 1;
+                    } else {
+                        back -= 1;
+                        assert_eq!(range.next_back(), Some(&expected[back]));
+                    }
+                }
+                assert_eq!(range.next(), None);
+                assert_eq!(range.next_back(), None);
+            }
+        }
+        assert_eq!(BTree::<i32, i32>::default().range(..).count(), 0);
+    }
+
     #[test]
     fn iter_std_b_tree() {
         let test_nums = 100_000usize;
@@ -494,4 +2375,317 @@ mod tests {
         });
         assert_eq!(num, b_tree.len());
     }
+
+    #[test]
+    fn snapshot() {
+        let mut rng = rand::thread_rng();
+        let mut b_tree = BTree::new(4);
+        for i in 0..1000 {
+            b_tree.add(i, i);
+        }
+        let snapshot = b_tree.snapshot();
+
+        // 只有被修改的路径上的节点被复制
+        b_tree.add(1000, 1000);
+        let (root, snapshot_root) = (
+            b_tree.root.as_ref().unwrap(),
+            snapshot.root.as_ref().unwrap(),
+        );
+        assert!(!Arc::ptr_eq(root, snapshot_root));
+        let shared = root
+            .children
+            .iter()
+            .zip(snapshot_root.children.iter())
+            .filter(|(child, snapshot_child)| Arc::ptr_eq(child, snapshot_child))
+            .count();
+        assert_eq!(shared, root.children.len() - 1);
+
+        let reader = {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || {
+                snapshot
+                    .iter()
+                    .map(|&(key, value)| key + value)
+                    .sum::<i32>()
+            })
+        };
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..1200);
+            match rng.gen_range(0..4) {
+                0 => {
+                    b_tree.remove(&key);
+                }
+                1 => {
+                    *b_tree.entry(key).or_insert(0) += 1;
+                }
+                2 => {
+                    if let Some(value) = b_tree.get_mut(&key) {
+                        *value = -1;
+                    }
+                }
+                _ => {
+                    b_tree.pop_first();
+                }
+            }
+        }
+        b_tree.iter_mut().for_each(|(_key, value)| *value *= 2);
+        assert!(b_tree.is_valid());
+
+        assert_eq!(
+            reader.join().unwrap(),
+            (0..1000).map(|i| i * 2).sum::<i32>()
+        );
+        assert!(snapshot.is_valid());
+        assert_eq!(snapshot.len(), 1000);
+        assert!(snapshot.iter().copied().eq((0..1000).map(|i| (i, i))));
+        assert_eq!(snapshot.get(&999), Some(&999));
+        assert_eq!(snapshot.range(10..20).count(), 10);
+    }
+
+    #[test]
+    fn clone() {
+        // clone 之后两棵树共享节点，各自修改互不影响
+        let mut b_tree = BTree::from_sorted_iter(4, 1.0, (0..200).map(|i| (i, i)));
+        let mut cloned = b_tree.clone();
+        b_tree.iter_mut().for_each(|(_key, value)| *value += 1);
+        cloned.remove(&0);
+        assert!(b_tree.iter().copied().eq((0..200).map(|i| (i, i + 1))));
+        assert!(cloned.into_iter().eq((1..200).map(|i| (i, i))));
+        b_tree.add(200, 201);
+        assert!(b_tree.into_iter().eq((0..201).map(|i| (i, i + 1))));
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        for &max_children_length in &[4, 5, 8] {
+            for &fill_factor in &[0.1, 0.5, 0.75, 1.0] {
+                for len in 0..300 {
+                    let b_tree = BTree::from_sorted_iter(
+                        max_children_length,
+                        fill_factor,
+                        (0..len).map(|i| (i, i)),
+                    );
+                    assert_eq!(b_tree.validate(), Ok(()));
+                    assert!(b_tree.iter().copied().eq((0..len).map(|i| (i, i))));
+                }
+            }
+        }
+
+        let b_tree = BTree::from_sorted_iter(4, 1.0, vec![(1, 'a'), (1, 'b'), (2, 'c')]);
+        assert_eq!(b_tree.len(), 2);
+        assert_eq!(b_tree.get(&1), Some(&'b'));
+
+        // 填充率为 1 时叶子节点是满的
+        let b_tree = BTree::from_sorted_iter(8, 1.0, (0..7 * 8 + 7).map(|i| (i, i)));
+        let root = b_tree.root.as_ref().unwrap();
+        assert_eq!(root.children.len(), 8);
+        assert!(root.children.iter().all(|leaf| leaf.pairs.len() == 7));
+    }
+
+    #[test]
+    #[should_panic(expected = "keys are not sorted")]
+    fn from_unsorted_iter() {
+        BTree::from_sorted_iter(4, 1.0, vec![(2, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn extend_sorted() {
+        let mut rng = rand::thread_rng();
+        let mut b_tree = BTree::new(6);
+        let mut std_tree = std::collections::BTreeMap::new();
+        for round in 0..20 {
+            let mut pairs = (0..rng.gen_range(0..500))
+                .map(|_| (rng.gen_range(0..2000), round))
+                .collect::<Vec<_>>();
+            pairs.sort_by_key(|&(key, _value)| key);
+            b_tree.extend_sorted(0.8, pairs.iter().copied());
+            std_tree.extend(pairs);
+            assert_eq!(b_tree.validate(), Ok(()));
+            assert!(b_tree
+                .iter()
+                .map(|(key, value)| (key, value))
+                .eq(std_tree.iter()));
+            b_tree.add(rng.gen_range(0..2000), -1);
+            b_tree.remove(&rng.gen_range(0..2000));
+            std_tree = b_tree.iter().copied().collect();
+        }
+    }
+
+    #[test]
+    fn extend_sorted_append() {
+        let mut rng = rand::thread_rng();
+        let left_most_leaf = |b_tree: &BTree<i32, i32>| {
+            let mut node = b_tree.root.clone().unwrap();
+            while let Some(first) = node.children.first() {
+                node = first.clone();
+            }
+            node
+        };
+        for &max_children_length in &[4, 5, 8] {
+            for &fill_factor in &[0.1, 0.5, 1.0] {
+                for len in 0..60 {
+                    let mut b_tree = BTree::new(max_children_length);
+                    let mut keys = (0..len).collect::<Vec<_>>();
+                    keys.shuffle(&mut rng);
+                    for key in keys {
+                        b_tree.add(key, key);
+                    }
+                    let snapshot = b_tree.snapshot();
+                    let mut end = len;
+                    for _ in 0..3 {
+                        let append_len = rng.gen_range(0..40);
+                        b_tree.extend_sorted(fill_factor, (end..end + append_len).map(|i| (i, i)));
+                        end += append_len;
+                        assert_eq!(b_tree.validate(), Ok(()));
+                        assert_eq!(b_tree.len(), end as usize);
+                        assert!(b_tree.iter().copied().eq((0..end).map(|i| (i, i))));
+                    }
+                    // 只沿最右侧路径追加，其余节点仍与快照共享
+                    if snapshot
+                        .root
+                        .as_ref()
+                        .is_some_and(|root| !root.children.is_empty())
+                    {
+                        assert!(Arc::ptr_eq(
+                            &left_most_leaf(&b_tree),
+                            &left_most_leaf(&snapshot)
+                        ));
+                    }
+                    assert!(snapshot.iter().copied().eq((0..len).map(|i| (i, i))));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn split_off_and_append() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let pairs = (0..rng.gen_range(0..300))
+                .map(|_| (rng.gen_range(0..1000), rng.gen_range(0..1000)))
+                .collect::<std::collections::BTreeMap<_, _>>();
+            let mut b_tree = BTree::new(5);
+            for (&key, &value) in &pairs {
+                b_tree.add(key, value);
+            }
+            let snapshot = b_tree.snapshot();
+
+            let key = rng.gen_range(-10..1010);
+            let mut right = b_tree.split_off(&key);
+            assert!(b_tree.is_valid());
+            assert!(right.is_valid());
+            assert!(b_tree.iter().all(|&(pair_key, _value)| pair_key < key));
+            assert!(right.iter().all(|&(pair_key, _value)| pair_key >= key));
+            assert_eq!(b_tree.len() + right.len(), pairs.len());
+
+            b_tree.append(&mut right);
+            assert!(right.is_empty());
+            assert!(b_tree.is_valid());
+            assert!(b_tree.iter().eq(snapshot.iter()));
+        }
+
+        let mut left = BTree::new(4);
+        let mut right = BTree::new(4);
+        for i in 0..10 {
+            left.add(i, 'l');
+            right.add(i + 5, 'r');
+        }
+        left.append(&mut right);
+        assert_eq!(left.len(), 15);
+        assert_eq!(left.get(&4), Some(&'l'));
+        assert_eq!(left.get(&5), Some(&'r'));
+    }
+
+    #[test]
+    fn split_off_and_join() {
+        let mut rng = rand::thread_rng();
+        for &max_children_length in &[4, 5, 8] {
+            for len in (0..200).step_by(7) {
+                let mut keys = (0..len).collect::<Vec<_>>();
+                keys.shuffle(&mut rng);
+                let mut b_tree = BTree::new(max_children_length);
+                for key in keys {
+                    b_tree.add(key, key);
+                }
+                for key in -1..=len {
+                    let mut left = b_tree.clone();
+                    let mut right = left.split_off(&key);
+                    assert_eq!(left.validate(), Ok(()));
+                    assert_eq!(right.validate(), Ok(()));
+                    assert_eq!(left.len(), key.clamp(0, len) as usize);
+                    assert!(left.keys().copied().eq(0..key.clamp(0, len)));
+                    assert!(right.keys().copied().eq(key.clamp(0, len)..len));
+
+                    // 键不重叠时在高度差处连接，无论 other 在左侧还是右侧
+                    if rng.gen_bool(0.5) {
+                        left.append(&mut right);
+                    } else {
+                        right.append(&mut left);
+                        std::mem::swap(&mut left, &mut right);
+                    }
+                    assert!(right.is_empty());
+                    assert_eq!(left.validate(), Ok(()));
+                    assert_eq!(left.len(), len as usize);
+                    assert!(left.keys().copied().eq(0..len));
+                }
+                assert!(b_tree.keys().copied().eq(0..len));
+            }
+        }
+
+        // 键交错时逐个插入或重建
+        for &other_len in &[3, 300] {
+            let mut b_tree = BTree::from_sorted_iter(6, 1.0, (0..1000).map(|i| (i * 2, 0)));
+            let mut other = BTree::from_sorted_iter(6, 0.5, (0..other_len).map(|i| (i * 3, 1)));
+            b_tree.append(&mut other);
+            assert_eq!(b_tree.validate(), Ok(()));
+            assert_eq!(b_tree.get(&3), Some(&1));
+            assert_eq!(b_tree.get(&2), Some(&0));
+            assert_eq!(
+                b_tree.len(),
+                1000 + (0..other_len)
+                    .filter(|i| i * 3 % 2 == 1 || i * 3 >= 2000)
+                    .count()
+            );
+        }
+    }
+
+    #[test]
+    fn set_operations() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let mut left = BTree::new(4);
+            let mut right = BTree::new(6);
+            let mut std_left = std::collections::BTreeSet::new();
+            let mut std_right = std::collections::BTreeSet::new();
+            for _ in 0..rng.gen_range(0..200) {
+                let key = rng.gen_range(0..300);
+                left.add(key, 'l');
+                std_left.insert(key);
+            }
+            for _ in 0..rng.gen_range(0..200) {
+                let key = rng.gen_range(0..300);
+                right.add(key, 'r');
+                std_right.insert(key);
+            }
+
+            assert!(left
+                .union(&right)
+                .map(|&(key, value)| (key, value))
+                .eq(std_left
+                    .union(&std_right)
+                    .map(|key| (*key, if std_left.contains(key) { 'l' } else { 'r' }))));
+            assert!(left
+                .intersection(&right)
+                .map(|&(key, value)| (key, value))
+                .eq(std_left.intersection(&std_right).map(|&key| (key, 'l'))));
+            assert!(left
+                .difference(&right)
+                .map(|&(key, _value)| key)
+                .eq(std_left.difference(&std_right).copied()));
+            assert!(left
+                .symmetric_difference(&right)
+                .map(|&(key, _value)| key)
+                .eq(std_left.symmetric_difference(&std_right).copied()));
+        }
+    }
 }
@@ -0,0 +1,180 @@
+use std::ops::RangeBounds;
+
+use crate::b_tree::BTree;
+
+// 有序集合，值存为 BTree 的键
+#[derive(Clone)]
+pub struct BTreeSet<T: Ord> {
+    tree: BTree<T, ()>,
+}
+
+impl<T: Ord> Default for BTreeSet<T> {
+    fn default() -> Self {
+        Self {
+            tree: BTree::default(),
+        }
+    }
+}
+
+impl<T: Ord> BTreeSet<T> {
+    pub fn new(max_children_length: usize) -> Self {
+        Self {
+            tree: BTree::new(max_children_length),
+        }
+    }
+    // 由升序的值在 O(n) 时间内构造集合，见 BTree::from_sorted_iter
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(
+        max_children_length: usize,
+        fill_factor: f64,
+        iter: I,
+    ) -> Self {
+        Self {
+            tree: BTree::from_sorted_iter(
+                max_children_length,
+                fill_factor,
+                iter.into_iter().map(|value| (value, ())),
+            ),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    pub fn is_valid(&self) -> bool {
+        self.tree.is_valid()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.tree.contains_key(value)
+    }
+    pub fn first(&self) -> Option<&T> {
+        self.tree.first_key_value().map(|(value, _)| value)
+    }
+    pub fn last(&self) -> Option<&T> {
+        self.tree.last_key_value().map(|(value, _)| value)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.tree.keys()
+    }
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> impl DoubleEndedIterator<Item = &T> {
+        self.tree.range(range).map(|(value, _)| value)
+    }
+
+    pub fn union<'t>(&'t self, other: &'t Self) -> impl Iterator<Item = &'t T> {
+        self.tree.union(&other.tree).map(|(value, _)| value)
+    }
+    pub fn intersection<'t>(&'t self, other: &'t Self) -> impl Iterator<Item = &'t T> {
+        self.tree.intersection(&other.tree).map(|(value, _)| value)
+    }
+    pub fn difference<'t>(&'t self, other: &'t Self) -> impl Iterator<Item = &'t T> {
+        self.tree.difference(&other.tree).map(|(value, _)| value)
+    }
+    pub fn symmetric_difference<'t>(&'t self, other: &'t Self) -> impl Iterator<Item = &'t T> {
+        self.tree
+            .symmetric_difference(&other.tree)
+            .map(|(value, _)| value)
+    }
+}
+
+impl<T: Ord + Clone> BTreeSet<T> {
+    // 返回 value 是否是新加入的
+    pub fn insert(&mut self, value: T) -> bool {
+        self.tree.add(value, ()).is_none()
+    }
+    // 返回 value 原先是否存在
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.tree.remove(value).is_some()
+    }
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.tree.pop_first().map(|(value, _)| value)
+    }
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.tree.pop_last().map(|(value, _)| value)
+    }
+
+    // 把不小于 value 的值移到新集合中
+    pub fn split_off(&mut self, value: &T) -> Self {
+        Self {
+            tree: self.tree.split_off(value),
+        }
+    }
+    // 把 other 的值全部移入 self
+    pub fn append(&mut self, other: &mut Self) {
+        self.tree.append(&mut other.tree);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::b_tree_set::*;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut set = BTreeSet::new(4);
+        for value in [5, 1, 3, 5, 2] {
+            set.insert(value);
+        }
+        assert_eq!(set.len(), 4);
+        assert!(!set.insert(3));
+        assert!(set.contains(&2));
+        assert_eq!(set.first(), Some(&1));
+        assert_eq!(set.last(), Some(&5));
+        assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 5]);
+        assert_eq!(
+            set.range(2..5).rev().copied().collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+
+        assert!(set.remove(&3));
+        assert!(!set.remove(&3));
+        assert_eq!(set.pop_first(), Some(1));
+        assert_eq!(set.pop_last(), Some(5));
+        assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![2]);
+        assert!(set.is_valid());
+    }
+
+    #[test]
+    fn random() {
+        let mut rng = rand::thread_rng();
+        let mut left = BTreeSet::new(5);
+        let mut right = BTreeSet::from_sorted_iter(5, 0.5, (0..100).map(|i| i * 3));
+        let mut std_left = std::collections::BTreeSet::new();
+        let mut std_right = (0..100)
+            .map(|i| i * 3)
+            .collect::<std::collections::BTreeSet<_>>();
+        for _ in 0..2000 {
+            let value = rng.gen_range(0..300);
+            if rng.gen_bool(0.3) {
+                assert_eq!(left.remove(&value), std_left.remove(&value));
+            } else {
+                assert_eq!(left.insert(value), std_left.insert(value));
+            }
+        }
+        assert!(left.is_valid());
+        assert!(left.iter().eq(std_left.iter()));
+        assert!(left.union(&right).eq(std_left.union(&std_right)));
+        assert!(left
+            .intersection(&right)
+            .eq(std_left.intersection(&std_right)));
+        assert!(left.difference(&right).eq(std_left.difference(&std_right)));
+        assert!(left
+            .symmetric_difference(&right)
+            .eq(std_left.symmetric_difference(&std_right)));
+
+        let mut split = left.split_off(&150);
+        let mut std_split = std_left.split_off(&150);
+        assert!(left.iter().eq(std_left.iter()));
+        assert!(split.iter().eq(std_split.iter()));
+        split.append(&mut right);
+        std_split.append(&mut std_right);
+        assert!(right.is_empty());
+        assert!(split.is_valid());
+        assert!(split.iter().eq(std_split.iter()));
+    }
+}
@@ -1,14 +1,25 @@
 use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::ptr::NonNull;
 
 type Tree<K, V> = Option<Box<Node<K, V>>>;
 
 struct Node<K: Ord, V> {
     key: K,
     value: V,
+    // 以该节点为根的子树的节点数
+    size: usize,
     left: Tree<K, V>,
     right: Tree<K, V>,
 }
 
+impl<K: Ord, V> Node<K, V> {
+    fn size(tree: &Tree<K, V>) -> usize {
+        tree.as_ref().map_or(0, |node| node.size)
+    }
+}
+
 pub struct BinarySearchTree<K: Ord, V> {
     root: Tree<K, V>,
     length: usize,
@@ -24,59 +35,851 @@ impl<K: Ord, V> Default for BinarySearchTree<K, V> {
 }
 
 impl<K: Ord, V> BinarySearchTree<K, V> {
-    fn add_rec(root: Tree<K, V>, (key, value): (K, V)) -> Box<Node<K, V>> {
-        match root {
-            None => Box::new(Node {
-                key,
-                value,
-                left: None,
-                right: None,
-            }),
-            Some(mut root) => {
-                match key.cmp(&root.key) {
-                    Ordering::Less => {
-                        root.left = Some(Self::add_rec(root.left, (key, value)));
-                    }
-                    Ordering::Equal => root.value = value,
-                    Ordering::Greater => {
-                        root.right = Some(Self::add_rec(root.right, (key, value)));
-                    }
-                }
-                root
+    fn find_slot<'a>(mut slot: &'a mut Tree<K, V>, target: &K) -> &'a mut Tree<K, V> {
+        loop {
+            match slot.as_ref().map(|node| target.cmp(&node.key)) {
+                None | Some(Ordering::Equal) => return slot,
+                Some(Ordering::Less) => slot = &mut slot.as_mut().unwrap().left,
+                Some(Ordering::Greater) => slot = &mut slot.as_mut().unwrap().right,
             }
         }
     }
 
-    pub fn add(&mut self, key: K, value: V) {
-        self.length += 1;
-        let root = self.root.take();
-        self.root = Some(Self::add_rec(root, (key, value)));
+    // 与 find_slot 相同，同时记下路径上（不含目标节点）每个节点 size 的位置，
+    // 插入或删除后据此更新 size，不必再查找一遍
+    fn find_slot_with_path<'a>(
+        mut slot: &'a mut Tree<K, V>,
+        target: &K,
+    ) -> (&'a mut Tree<K, V>, Vec<NonNull<usize>>) {
+        let mut path = Vec::new();
+        loop {
+            let less = match slot.as_ref().map(|node| target.cmp(&node.key)) {
+                None | Some(Ordering::Equal) => return (slot, path),
+                Some(ordering) => ordering == Ordering::Less,
+            };
+            let node = slot.as_mut().unwrap();
+            path.push(NonNull::from(&mut node.size));
+            slot = if less {
+                &mut node.left
+            } else {
+                &mut node.right
+            };
+        }
     }
 
-    fn find_rec<'b>(root: &'b Tree<K, V>, target: &K) -> Option<&'b V> {
-        match root {
-            None => None,
-            Some(root) => match target.cmp(&root.key) {
-                Ordering::Less => Self::find_rec(&root.left, target),
-                Ordering::Equal => Some(&root.value),
-                Ordering::Greater => Self::find_rec(&root.right, target),
-            },
+    fn resize_path(path: Vec<NonNull<usize>>, delta: isize) {
+        for mut size in path {
+            // path 中的指针都指向 slot 的祖先节点，entry 持有整棵树的可变借用，期间它们一直有效
+            let size = unsafe { size.as_mut() };
+            *size = size.wrapping_add_signed(delta);
         }
     }
 
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let (slot, path) = Self::find_slot_with_path(&mut self.root, &key);
+        let occupied = slot.is_some();
+        let tree = EntryTree {
+            slot,
+            path,
+            length: &mut self.length,
+        };
+        if occupied {
+            Entry::Occupied(OccupiedEntry { key, tree })
+        } else {
+            Entry::Vacant(VacantEntry { key, tree })
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn add(&mut self, key: K, value: V) -> Option<V> {
+        self.insert(key, value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn contains_key(&self, target: &K) -> bool {
+        self.find(target).is_some()
+    }
+
+    pub fn get_mut(&mut self, target: &K) -> Option<&mut V> {
+        Self::find_slot(&mut self.root, target)
+            .as_mut()
+            .map(|node| &mut node.value)
+    }
+
     pub fn find(&self, target: &K) -> Option<&V> {
-        Self::find_rec(&self.root, target)
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            tree = match target.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Greater => &node.right,
+            };
+        }
+        None
+    }
+
+    fn take_min(mut slot: &mut Tree<K, V>) -> Box<Node<K, V>> {
+        while slot.as_ref().unwrap().left.is_some() {
+            let node = slot.as_mut().unwrap();
+            node.size -= 1;
+            slot = &mut node.left;
+        }
+        let mut min = slot.take().unwrap();
+        *slot = min.right.take();
+        min
+    }
+
+    fn take_node(slot: &mut Tree<K, V>) -> Box<Node<K, V>> {
+        let mut node = slot.take().unwrap();
+        *slot = match (node.left.take(), node.right.take()) {
+            (None, None) => None,
+            (Some(child), None) | (None, Some(child)) => Some(child),
+            (Some(left), Some(right)) => {
+                // 用中序后继替换被删除的节点
+                let mut right = Some(right);
+                let mut successor = Self::take_min(&mut right);
+                successor.size = left.size + Node::size(&right) + 1;
+                successor.left = Some(left);
+                successor.right = right;
+                Some(successor)
+            }
+        };
+        node
+    }
+
+    pub fn remove(&mut self, target: &K) -> Option<V> {
+        let (slot, path) = Self::find_slot_with_path(&mut self.root, target);
+        slot.as_ref()?;
+        self.length -= 1;
+        Self::resize_path(path, -1);
+        Some(Self::take_node(slot).value)
     }
 
-    fn walk_rec(root: &Tree<K, V>, callback: &mut impl FnMut((&K, &V))) {
-        if let Some(root) = root {
-            Self::walk_rec(&root.left, callback);
-            callback((&root.key, &root.value));
-            Self::walk_rec(&root.right, callback);
+    /// 返回第 k 小（从 0 开始）的键值对
+    pub fn select(&self, mut k: usize) -> Option<(&K, &V)> {
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            let left_size = Node::size(&node.left);
+            tree = match k.cmp(&left_size) {
+                Ordering::Less => &node.left,
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Greater => {
+                    k -= left_size + 1;
+                    &node.right
+                }
+            };
+        }
+        None
+    }
+
+    /// 返回小于 target 的键的个数
+    pub fn rank(&self, target: &K) -> usize {
+        let mut rank = 0;
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            tree = match target.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Equal => return rank + Node::size(&node.left),
+                Ordering::Greater => {
+                    rank += Node::size(&node.left) + 1;
+                    &node.right
+                }
+            };
+        }
+        rank
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(left) = &node.left {
+            node = left;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_ref()?;
+        while let Some(right) = &node.right {
+            node = right;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    // 小于（或等于，当 inclusive 时）target 的最大键
+    fn lower(&self, target: &K, inclusive: bool) -> Option<(&K, &V)> {
+        let mut found = None;
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            tree = match node.key.cmp(target) {
+                Ordering::Less => {
+                    found = Some((&node.key, &node.value));
+                    &node.right
+                }
+                Ordering::Equal if inclusive => return Some((&node.key, &node.value)),
+                _ => &node.left,
+            };
+        }
+        found
+    }
+
+    // 大于（或等于，当 inclusive 时）target 的最小键
+    fn upper(&self, target: &K, inclusive: bool) -> Option<(&K, &V)> {
+        let mut found = None;
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            tree = match node.key.cmp(target) {
+                Ordering::Greater => {
+                    found = Some((&node.key, &node.value));
+                    &node.left
+                }
+                Ordering::Equal if inclusive => return Some((&node.key, &node.value)),
+                _ => &node.right,
+            };
+        }
+        found
+    }
+
+    /// 小于等于 target 的最大键
+    pub fn floor(&self, target: &K) -> Option<(&K, &V)> {
+        self.lower(target, true)
+    }
+
+    /// 大于等于 target 的最小键
+    pub fn ceiling(&self, target: &K) -> Option<(&K, &V)> {
+        self.upper(target, true)
+    }
+
+    /// 小于 target 的最大键
+    pub fn predecessor(&self, target: &K) -> Option<(&K, &V)> {
+        self.lower(target, false)
+    }
+
+    /// 大于 target 的最小键
+    pub fn successor(&self, target: &K) -> Option<(&K, &V)> {
+        self.upper(target, false)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, R> {
+        let mut iter = Range {
+            stack: vec![],
+            range,
+        };
+        iter.push_left(&self.root);
+        iter
+    }
+
+    /// 由严格升序的键值对在 O(n) 时间内构造一棵完全平衡的树
+    pub fn from_sorted(iter: impl IntoIterator<Item = (K, V)>) -> Self {
+        let pairs: Vec<(K, V)> = iter.into_iter().collect();
+        assert!(pairs.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let length = pairs.len();
+        let root = Self::build_balanced(&mut pairs.into_iter(), length);
+        Self { root, length }
+    }
+    // 按中序依次从 pairs 中取出 size 个键值对建树，递归深度为 O(log n)
+    fn build_balanced(pairs: &mut impl Iterator<Item = (K, V)>, size: usize) -> Tree<K, V> {
+        if size == 0 {
+            return None;
         }
+        let left_size = size / 2;
+        let left = Self::build_balanced(pairs, left_size);
+        let (key, value) = pairs.next().unwrap();
+        let right = Self::build_balanced(pairs, size - left_size - 1);
+        Some(Box::new(Node {
+            key,
+            value,
+            size,
+            left,
+            right,
+        }))
     }
 
-    pub fn walk(&self, mut callback: impl FnMut((&K, &V))) {
-        Self::walk_rec(&self.root, &mut callback)
+    fn rotate_left(slot: &mut Tree<K, V>) {
+        let mut node = slot.take().unwrap();
+        let mut right = node.right.take().unwrap();
+        node.right = right.left.take();
+        node.size = Node::size(&node.left) + Node::size(&node.right) + 1;
+        right.size = node.size + Node::size(&right.right) + 1;
+        right.left = Some(node);
+        *slot = Some(right);
+    }
+
+    fn rotate_right(slot: &mut Tree<K, V>) {
+        let mut node = slot.take().unwrap();
+        let mut left = node.left.take().unwrap();
+        node.left = left.right.take();
+        node.size = Node::size(&node.left) + Node::size(&node.right) + 1;
+        left.size = node.size + Node::size(&left.left) + 1;
+        left.right = Some(node);
+        *slot = Some(left);
+    }
+
+    // 沿右链对前 count 个节点做左旋
+    fn compress(&mut self, count: usize) {
+        let mut slot = &mut self.root;
+        for _ in 0..count {
+            Self::rotate_left(slot);
+            slot = &mut slot.as_mut().unwrap().right;
+        }
+    }
+
+    /// Day-Stout-Warren 算法：先右旋成只有右子树的链，再逐层左旋压缩，额外空间 O(1)
+    pub fn rebalance(&mut self) {
+        let mut slot = &mut self.root;
+        while slot.is_some() {
+            if slot.as_ref().unwrap().left.is_some() {
+                Self::rotate_right(slot);
+            } else {
+                slot = &mut slot.as_mut().unwrap().right;
+            }
+        }
+
+        // 最底层之外是一棵满二叉树，先把多出来的节点压到最底层
+        let leaves = self.length + 1 - (1 << (self.length + 1).ilog2());
+        self.compress(leaves);
+        let mut size = self.length - leaves;
+        while size > 1 {
+            size /= 2;
+            self.compress(size);
+        }
+    }
+
+    pub fn walk(&self, callback: impl FnMut((&K, &V))) {
+        self.iter().for_each(callback)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter {
+            front: vec![],
+            back: vec![],
+            remain_len: self.length,
+        };
+        iter.push_front(&self.root);
+        iter.push_back(&self.root);
+        iter
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        let mut iter = IterMut {
+            stack: vec![],
+            remain_len: self.length,
+        };
+        iter.push_left(&mut self.root);
+        iter
+    }
+
+    pub fn preorder(&self) -> Preorder<'_, K, V> {
+        Preorder {
+            stack: self.root.as_deref().into_iter().collect(),
+        }
+    }
+
+    pub fn postorder(&self) -> Postorder<'_, K, V> {
+        Postorder {
+            stack: self
+                .root
+                .as_deref()
+                .map(|root| (root, false))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    pub fn level_order(&self) -> LevelOrder<'_, K, V> {
+        LevelOrder {
+            queue: self.root.as_deref().into_iter().collect(),
+        }
+    }
+}
+
+impl<K: Ord, V> Drop for BinarySearchTree<K, V> {
+    fn drop(&mut self) {
+        // 逐个拆下节点，避免退化成链表的树在递归析构时栈溢出
+        let mut stack: Vec<Box<Node<K, V>>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+pub enum Entry<'a, K: Ord, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+// entry 持有查找到的位置，插入和删除时需要更新路径上每个节点的 size
+struct EntryTree<'a, K: Ord, V> {
+    slot: &'a mut Tree<K, V>,
+    path: Vec<NonNull<usize>>,
+    length: &'a mut usize,
+}
+
+pub struct OccupiedEntry<'a, K: Ord, V> {
+    key: K,
+    tree: EntryTree<'a, K, V>,
+}
+
+pub struct VacantEntry<'a, K: Ord, V> {
+    key: K,
+    tree: EntryTree<'a, K, V>,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.tree.slot.as_ref().unwrap().value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.tree.slot.as_mut().unwrap().value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        let slot = self.tree.slot;
+        &mut slot.as_mut().unwrap().value
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        *self.tree.length -= 1;
+        BinarySearchTree::<K, V>::resize_path(self.tree.path, -1);
+        let node = BinarySearchTree::take_node(self.tree.slot);
+        (node.key, node.value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        *self.tree.length += 1;
+        BinarySearchTree::<K, V>::resize_path(self.tree.path, 1);
+        let node = self.tree.slot.insert(Box::new(Node {
+            key: self.key,
+            value,
+            size: 1,
+            left: None,
+            right: None,
+        }));
+        &mut node.value
+    }
+}
+
+pub struct Iter<'t, K: Ord, V> {
+    front: Vec<&'t Node<K, V>>,
+    back: Vec<&'t Node<K, V>>,
+    remain_len: usize,
+}
+
+impl<'t, K: Ord, V> Iter<'t, K, V> {
+    fn push_front(&mut self, mut tree: &'t Tree<K, V>) {
+        while let Some(node) = tree {
+            self.front.push(node);
+            tree = &node.left;
+        }
+    }
+    fn push_back(&mut self, mut tree: &'t Tree<K, V>) {
+        while let Some(node) = tree {
+            self.back.push(node);
+            tree = &node.right;
+        }
+    }
+}
+
+impl<'t, K: Ord, V> Iterator for Iter<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remain_len == 0 {
+            return None;
+        }
+        self.remain_len -= 1;
+        let node = self.front.pop().unwrap();
+        self.push_front(&node.right);
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+impl<'t, K: Ord, V> DoubleEndedIterator for Iter<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remain_len == 0 {
+            return None;
+        }
+        self.remain_len -= 1;
+        let node = self.back.pop().unwrap();
+        self.push_back(&node.left);
+        Some((&node.key, &node.value))
+    }
+}
+
+pub struct Range<'t, K: Ord, V, R: RangeBounds<K>> {
+    stack: Vec<&'t Node<K, V>>,
+    range: R,
+}
+
+impl<'t, K: Ord, V, R: RangeBounds<K>> Range<'t, K, V, R> {
+    fn below_start(&self, key: &K) -> bool {
+        match self.range.start_bound() {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        }
+    }
+    fn after_end(&self, key: &K) -> bool {
+        match self.range.end_bound() {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        }
+    }
+    // 只把范围内的节点入栈，范围外节点的另一侧子树直接跳过
+    fn push_left(&mut self, mut tree: &'t Tree<K, V>) {
+        while let Some(node) = tree {
+            if self.below_start(&node.key) {
+                tree = &node.right;
+            } else {
+                if !self.after_end(&node.key) {
+                    self.stack.push(node);
+                }
+                tree = &node.left;
+            }
+        }
+    }
+}
+
+impl<'t, K: Ord, V, R: RangeBounds<K>> Iterator for Range<'t, K, V, R> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(&node.right);
+        Some((&node.key, &node.value))
+    }
+}
+
+pub struct IterMut<'t, K: Ord, V> {
+    stack: Vec<(&'t K, &'t mut V, &'t mut Tree<K, V>)>,
+    remain_len: usize,
+}
+
+impl<'t, K: Ord, V> IterMut<'t, K, V> {
+    fn push_left(&mut self, mut tree: &'t mut Tree<K, V>) {
+        while let Some(node) = tree {
+            let Node {
+                key,
+                value,
+                left,
+                right,
+                ..
+            } = &mut **node;
+            self.stack.push((key, value, right));
+            tree = left;
+        }
+    }
+}
+
+impl<'t, K: Ord, V> Iterator for IterMut<'t, K, V> {
+    type Item = (&'t K, &'t mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value, right) = self.stack.pop()?;
+        self.remain_len -= 1;
+        self.push_left(right);
+        Some((key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+pub struct IntoIter<K: Ord, V> {
+    stack: Vec<Box<Node<K, V>>>,
+    remain_len: usize,
+}
+
+impl<K: Ord, V> IntoIter<K, V> {
+    fn push_left(&mut self, mut tree: Tree<K, V>) {
+        while let Some(mut node) = tree {
+            tree = node.left.take();
+            self.stack.push(node);
+        }
+    }
+}
+
+impl<K: Ord, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut node = self.stack.pop()?;
+        self.remain_len -= 1;
+        self.push_left(node.right.take());
+        Some((node.key, node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+impl<K: Ord, V> Drop for IntoIter<K, V> {
+    fn drop(&mut self) {
+        while self.next().is_some() {}
+    }
+}
+
+impl<K: Ord, V> IntoIterator for BinarySearchTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        let mut iter = IntoIter {
+            stack: vec![],
+            remain_len: self.length,
+        };
+        iter.push_left(self.root.take());
+        iter
+    }
+}
+
+impl<'t, K: Ord, V> IntoIterator for &'t BinarySearchTree<K, V> {
+    type Item = (&'t K, &'t V);
+    type IntoIter = Iter<'t, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'t, K: Ord, V> IntoIterator for &'t mut BinarySearchTree<K, V> {
+    type Item = (&'t K, &'t mut V);
+    type IntoIter = IterMut<'t, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct Preorder<'t, K: Ord, V> {
+    stack: Vec<&'t Node<K, V>>,
+}
+
+impl<'t, K: Ord, V> Iterator for Preorder<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        if let Some(right) = &node.right {
+            self.stack.push(right);
+        }
+        if let Some(left) = &node.left {
+            self.stack.push(left);
+        }
+        Some((&node.key, &node.value))
+    }
+}
+
+pub struct Postorder<'t, K: Ord, V> {
+    // 第二个值表示该节点的子节点是否已入栈
+    stack: Vec<(&'t Node<K, V>, bool)>,
+}
+
+impl<'t, K: Ord, V> Iterator for Postorder<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, children_pushed) = self.stack.pop()?;
+            if children_pushed {
+                return Some((&node.key, &node.value));
+            }
+            self.stack.push((node, true));
+            if let Some(right) = &node.right {
+                self.stack.push((right, false));
+            }
+            if let Some(left) = &node.left {
+                self.stack.push((left, false));
+            }
+        }
+    }
+}
+
+pub struct LevelOrder<'t, K: Ord, V> {
+    queue: VecDeque<&'t Node<K, V>>,
+}
+
+impl<'t, K: Ord, V> Iterator for LevelOrder<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+        if let Some(left) = &node.left {
+            self.queue.push_back(left);
+        }
+        if let Some(right) = &node.right {
+            self.queue.push_back(right);
+        }
+        Some((&node.key, &node.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::binary::*;
+
+    // 按升序插入会得到只有右子树的链表，这里直接构造同样形状的树，
+    // 逐个插入需要 O(n^2) 的时间
+    fn sorted_chain(num: u64) -> BinarySearchTree<u64, u64> {
+        let mut root = None;
+        for key in (0..num).rev() {
+            root = Some(Box::new(Node {
+                key,
+                value: key,
+                size: (num - key) as usize,
+                left: None,
+                right: root,
+            }));
+        }
+        BinarySearchTree {
+            root,
+            length: num as usize,
+        }
+    }
+
+    #[test]
+    fn degenerate() {
+        const NUM: u64 = 1_000_000;
+        let mut tree = sorted_chain(NUM);
+
+        assert_eq!(tree.find(&(NUM - 1)), Some(&(NUM - 1)));
+        assert_eq!(tree.find(&NUM), None);
+        assert_eq!(tree.insert(NUM, NUM), None);
+        assert_eq!(tree.insert(NUM, NUM), Some(NUM));
+        *tree.get_mut(&(NUM - 1)).unwrap() += 1;
+        assert_eq!(tree.remove(&(NUM - 1)), Some(NUM));
+        assert_eq!(tree.remove(&(NUM - 1)), None);
+        assert_eq!(tree.len(), NUM as usize);
+        assert_eq!(tree.rank(&NUM), NUM as usize - 1);
+        assert_eq!(tree.select(NUM as usize - 1), Some((&NUM, &NUM)));
+
+        let mut count = 0;
+        tree.walk(|_| count += 1);
+        assert_eq!(count, NUM as usize);
+        assert_eq!(tree.iter().next_back(), Some((&NUM, &NUM)));
+        assert_eq!(tree.iter_mut().count(), NUM as usize);
+        assert_eq!(tree.preorder().count(), NUM as usize);
+        assert_eq!(tree.postorder().count(), NUM as usize);
+        assert_eq!(tree.level_order().count(), NUM as usize);
+
+        let mut into_iter = tree.into_iter();
+        assert_eq!(into_iter.next(), Some((0, 0)));
+        drop(into_iter);
+
+        drop(sorted_chain(NUM));
+    }
+
+    fn height<K: Ord, V>(tree: &Tree<K, V>) -> usize {
+        tree.as_ref().map_or(0, |node| {
+            std::cmp::max(height(&node.left), height(&node.right)) + 1
+        })
+    }
+
+    #[test]
+    fn balanced() {
+        for num in 0..300u64 {
+            let tree = BinarySearchTree::from_sorted((0..num).map(|i| (i, i)));
+            assert_eq!(
+                height(&tree.root),
+                (num + 1).next_power_of_two().trailing_zeros() as usize
+            );
+            let mut tree = sorted_chain(num);
+            tree.rebalance();
+            assert_eq!(
+                height(&tree.root),
+                (num + 1).next_power_of_two().trailing_zeros() as usize
+            );
+            assert!(tree.iter().map(|(&k, _)| k).eq(0..num));
+            assert!((0..num).all(|i| tree.rank(&i) == i as usize));
+        }
+
+        const NUM: u64 = 1_000_000;
+        let mut tree = sorted_chain(NUM);
+        tree.rebalance();
+        assert_eq!(height(&tree.root), 20);
+        assert_eq!(
+            tree.select(NUM as usize / 3),
+            Some((&(NUM / 3), &(NUM / 3)))
+        );
     }
 }
@@ -136,21 +136,21 @@ enum Distance {
 
 impl PartialOrd for Distance {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self, other) {
-            (Distance::Infinite, Distance::Infinite) => Some(Ordering::Equal),
-
-            (Distance::Number(_weight), Distance::Infinite) => Some(Ordering::Less),
-            (Distance::Infinite, Distance::Number(_weight)) => Some(Ordering::Greater),
-            (Distance::Number(weight_self), Distance::Number(weight_other)) => {
-                weight_self.partial_cmp(weight_other)
-            }
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Distance {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+        match (self, other) {
+            (Distance::Infinite, Distance::Infinite) => Ordering::Equal,
+
+            (Distance::Number(_weight), Distance::Infinite) => Ordering::Less,
+            (Distance::Infinite, Distance::Number(_weight)) => Ordering::Greater,
+            (Distance::Number(weight_self), Distance::Number(weight_other)) => {
+                weight_self.cmp(weight_other)
+            }
+        }
     }
 }
 
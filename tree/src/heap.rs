@@ -49,7 +49,7 @@ impl<T: Ord> Heap<T> {
     }
     fn fix_up(&mut self, mut index: usize) {
         while index > 0 {
-            let parent_index = (index + 1) / 2 - 1;
+            let parent_index = index.div_ceil(2) - 1;
             if self.heap[parent_index] <= self.heap[index] {
                 break;
             }
@@ -59,7 +59,7 @@ impl<T: Ord> Heap<T> {
         }
     }
 
-    pub fn drain(&mut self) -> DrainIter<T> {
+    pub fn drain(&mut self) -> DrainIter<'_, T> {
         DrainIter { heap: self }
     }
 }
@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+// 树违反的不变式，`path` 是从根开始每层所走的子节点下标
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum InvariantViolation {
+    RedRoot,
+    // `path` 处的红色节点的父节点也是红色
+    RedRed {
+        path: Vec<usize>,
+    },
+    // `path` 处节点的各子树黑高不同
+    BlackHeight {
+        path: Vec<usize>,
+        left: usize,
+        right: usize,
+    },
+    // `path` 处节点的某个键与相邻键或祖先的键顺序不对
+    KeyOrder {
+        path: Vec<usize>,
+    },
+    // `path` 处节点没有指回父节点
+    ParentLink {
+        path: Vec<usize>,
+    },
+    // `path` 处节点的子节点过少或过多，叶子按 `pairs + 1` 计
+    FanOut {
+        path: Vec<usize>,
+        children: usize,
+        min: usize,
+        max: usize,
+    },
+    // `path` 处内部节点的子节点数不比键值对数多一
+    ChildCount {
+        path: Vec<usize>,
+        pairs: usize,
+        children: usize,
+    },
+    // `path` 处叶子的深度与第一个叶子不同
+    LeafDepth {
+        path: Vec<usize>,
+        depth: usize,
+        expected: usize,
+    },
+    // `path` 处叶子没有链接到相邻叶子
+    LeafLink {
+        path: Vec<usize>,
+    },
+    // 记录的长度或子树大小与可达元素个数不一致
+    Length {
+        recorded: usize,
+        counted: usize,
+    },
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvariantViolation::RedRoot => write!(f, "root is red"),
+            InvariantViolation::RedRed { path } => {
+                write!(f, "red node at {:?} has a red parent", path)
+            }
+            InvariantViolation::BlackHeight { path, left, right } => write!(
+                f,
+                "black heights {} and {} differ under {:?}",
+                left, right, path
+            ),
+            InvariantViolation::KeyOrder { path } => write!(f, "keys out of order at {:?}", path),
+            InvariantViolation::ParentLink { path } => {
+                write!(f, "node at {:?} has a wrong parent link", path)
+            }
+            InvariantViolation::FanOut {
+                path,
+                children,
+                min,
+                max,
+            } => write!(
+                f,
+                "node at {:?} has {} children, expected {}..={}",
+                path, children, min, max
+            ),
+            InvariantViolation::ChildCount {
+                path,
+                pairs,
+                children,
+            } => write!(
+                f,
+                "node at {:?} has {} pairs but {} children",
+                path, pairs, children
+            ),
+            InvariantViolation::LeafDepth {
+                path,
+                depth,
+                expected,
+            } => write!(
+                f,
+                "leaf at {:?} has depth {}, expected {}",
+                path, depth, expected
+            ),
+            InvariantViolation::LeafLink { path } => {
+                write!(f, "leaf at {:?} is not linked to its neighbours", path)
+            }
+            InvariantViolation::Length { recorded, counted } => write!(
+                f,
+                "length is {} but {} entries are reachable",
+                recorded, counted
+            ),
+        }
+    }
+}
+
+impl Error for InvariantViolation {}
@@ -1,6 +1,13 @@
+pub mod avl;
+pub mod b_plus_tree;
 pub mod b_tree;
+pub mod b_tree_set;
 pub mod binary;
 pub mod graph;
 pub mod heap;
+pub mod invariant;
+pub mod paged_b_tree;
+pub mod persistent_red_black;
 pub mod red_black;
+pub mod red_black_multi_map;
 pub mod trie;
@@ -0,0 +1,892 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::b_tree::{
+    add_pair, borrow_from_left, borrow_from_right, merge, plan_rebalance, Branch, Rebalance,
+};
+
+type Pair<K, V> = (K, V);
+type PageId = u64;
+
+// 第 0 页为文件头，页号 0 同时表示空的根节点和空闲页链表的结尾
+const HEADER: PageId = 0;
+const MAGIC: &[u8; 4] = b"PBTR";
+// magic、page_size、max_children_length、root、length、page_count、free_head
+const HEADER_LEN: usize = 4 + 4 + 4 + 8 * 4;
+
+// 节点编码中与键值对无关的部分：键值对个数、子节点个数及子节点页号
+fn node_overhead(max_children_length: usize) -> usize {
+    2 + 2 + 8 * max_children_length
+}
+
+// 键和值写入页时的编码
+pub trait Codec: Sized {
+    fn encode(&self, bytes: &mut Vec<u8>);
+    // 从 bytes 开头读出一个值，并跳过已读的部分
+    fn decode(bytes: &mut &[u8]) -> io::Result<Self>;
+}
+
+fn take_bytes<'b>(bytes: &mut &'b [u8], len: usize) -> io::Result<&'b [u8]> {
+    if bytes.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated page",
+        ));
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+macro_rules! impl_codec_for_int {
+    ($($int:ty),*) => {$(
+        impl Codec for $int {
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(bytes: &mut &[u8]) -> io::Result<Self> {
+                let taken = take_bytes(bytes, std::mem::size_of::<Self>())?;
+                Ok(Self::from_le_bytes(taken.try_into().unwrap()))
+            }
+        }
+    )*};
+}
+
+impl_codec_for_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Codec for String {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        (self.len() as u32).encode(bytes);
+        bytes.extend_from_slice(self.as_bytes());
+    }
+    fn decode(bytes: &mut &[u8]) -> io::Result<Self> {
+        let len = u32::decode(bytes)? as usize;
+        let taken = take_bytes(bytes, len)?;
+        String::from_utf8(taken.to_vec())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+struct PageNode<K, V> {
+    pairs: Vec<Pair<K, V>>,
+    children: Vec<PageId>,
+}
+
+impl<K, V> Branch for PageNode<K, V> {
+    type Pair = Pair<K, V>;
+    type Child = PageId;
+
+    fn from_parts(pairs: Vec<Self::Pair>, children: Vec<Self::Child>) -> Self {
+        Self { pairs, children }
+    }
+    fn parts(&mut self) -> (&mut Vec<Self::Pair>, &mut Vec<Self::Child>) {
+        (&mut self.pairs, &mut self.children)
+    }
+}
+
+impl<K: Codec, V: Codec> PageNode<K, V> {
+    fn encode(&self, page_size: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        (self.pairs.len() as u16).encode(&mut bytes);
+        (self.children.len() as u16).encode(&mut bytes);
+        for child in &self.children {
+            child.encode(&mut bytes);
+        }
+        for (key, value) in &self.pairs {
+            key.encode(&mut bytes);
+            value.encode(&mut bytes);
+        }
+        if bytes.len() > page_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "node does not fit in a page",
+            ));
+        }
+        bytes.resize(page_size, 0);
+        Ok(bytes)
+    }
+    fn decode(mut bytes: &[u8]) -> io::Result<Self> {
+        let bytes = &mut bytes;
+        let pairs_len = u16::decode(bytes)? as usize;
+        let children_len = u16::decode(bytes)? as usize;
+        let children = (0..children_len)
+            .map(|_| PageId::decode(bytes))
+            .collect::<io::Result<_>>()?;
+        let pairs = (0..pairs_len)
+            .map(|_| Ok((K::decode(bytes)?, V::decode(bytes)?)))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { pairs, children })
+    }
+}
+
+struct Cached<K, V> {
+    node: PageNode<K, V>,
+    dirty: bool,
+    used: u64,
+}
+
+// 缓存解码后的节点，超出容量时淘汰最久未使用的，脏节点在淘汰或 flush 时写回
+struct Pager<K, V> {
+    file: File,
+    page_size: usize,
+    page_count: u64,
+    free_head: PageId,
+    // 已释放但还没有写入空闲页链表的页，flush 时写回
+    freed: Vec<PageId>,
+    capacity: usize,
+    cache: HashMap<PageId, Cached<K, V>>,
+    // 最近使用时间到页号
+    lru: BTreeMap<u64, PageId>,
+    clock: u64,
+    // 修改操作期间用到的节点不会被淘汰，保证修改时它们都在缓存中
+    pinned_after: Option<u64>,
+    // 测试时在第几次读写页时出错
+    #[cfg(test)]
+    fail_after: Option<usize>,
+}
+
+impl<K: Codec, V: Codec> Pager<K, V> {
+    fn new(
+        file: File,
+        page_size: usize,
+        page_count: u64,
+        free_head: PageId,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            file,
+            page_size,
+            page_count,
+            free_head,
+            freed: vec![],
+            capacity,
+            cache: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            pinned_after: None,
+            #[cfg(test)]
+            fail_after: None,
+        }
+    }
+
+    #[cfg(test)]
+    fn inject_failure(&mut self) -> io::Result<()> {
+        match &mut self.fail_after {
+            Some(0) => Err(io::Error::other("injected failure")),
+            Some(count) => {
+                *count -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+    fn read_page(&mut self, id: PageId) -> io::Result<Vec<u8>> {
+        #[cfg(test)]
+        self.inject_failure()?;
+        let mut bytes = vec![0; self.page_size];
+        self.file
+            .seek(SeekFrom::Start(id * self.page_size as u64))?;
+        self.file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+    fn write_page(&mut self, id: PageId, bytes: &[u8]) -> io::Result<()> {
+        #[cfg(test)]
+        self.inject_failure()?;
+        self.file
+            .seek(SeekFrom::Start(id * self.page_size as u64))?;
+        self.file.write_all(bytes)
+    }
+
+    // 修改操作先用 node 读入所有会用到的节点，读写都成功后再用 cached_mut 修改，修改过程不会失败
+    // 固定前先淘汰多余的节点，新建的节点只会在下一次淘汰时写回
+    fn pin(&mut self) -> io::Result<()> {
+        self.evict()?;
+        self.pinned_after = Some(self.clock);
+        Ok(())
+    }
+    fn unpin(&mut self) {
+        self.pinned_after = None;
+    }
+
+    fn node(&mut self, id: PageId) -> io::Result<&PageNode<K, V>> {
+        self.load(id)?;
+        self.clock += 1;
+        let cached = self.cache.get_mut(&id).unwrap();
+        self.lru.remove(&cached.used);
+        cached.used = self.clock;
+        self.lru.insert(cached.used, id);
+        Ok(&cached.node)
+    }
+    // 已经读入缓存的节点
+    fn cached(&self, id: PageId) -> &PageNode<K, V> {
+        &self.cache[&id].node
+    }
+    fn cached_mut(&mut self, id: PageId) -> &mut PageNode<K, V> {
+        let cached = self.cache.get_mut(&id).unwrap();
+        cached.dirty = true;
+        &mut cached.node
+    }
+    fn cached_disjoint_mut<const N: usize>(
+        &mut self,
+        ids: [PageId; N],
+    ) -> [&mut PageNode<K, V>; N] {
+        self.cache.get_disjoint_mut(ids.each_ref()).map(|cached| {
+            let cached = cached.unwrap();
+            cached.dirty = true;
+            &mut cached.node
+        })
+    }
+    fn load(&mut self, id: PageId) -> io::Result<()> {
+        if self.cache.contains_key(&id) {
+            return Ok(());
+        }
+        self.evict()?;
+        let node = PageNode::decode(&self.read_page(id)?)?;
+        self.insert(id, node, false);
+        Ok(())
+    }
+    fn insert(&mut self, id: PageId, node: PageNode<K, V>, dirty: bool) {
+        self.clock += 1;
+        self.lru.insert(self.clock, id);
+        self.cache.insert(
+            id,
+            Cached {
+                node,
+                dirty,
+                used: self.clock,
+            },
+        );
+    }
+    // 为读入新节点腾出位置，写回失败时被淘汰的节点仍留在缓存中。被固定的节点不会被淘汰，
+    // 一次修改操作期间缓存可能暂时超出容量
+    fn evict(&mut self) -> io::Result<()> {
+        while self.cache.len() >= self.capacity {
+            let (&used, &evicted) = self.lru.first_key_value().unwrap();
+            if self
+                .pinned_after
+                .is_some_and(|pinned_after| used > pinned_after)
+            {
+                break;
+            }
+            let cached = &self.cache[&evicted];
+            if cached.dirty {
+                let bytes = cached.node.encode(self.page_size)?;
+                self.write_page(evicted, &bytes)?;
+            }
+            self.lru.remove(&used);
+            self.cache.remove(&evicted);
+        }
+        Ok(())
+    }
+
+    // 先算出要分配的页，读取空闲页都成功后才修改空闲页链表，失败时不分配任何页
+    fn alloc(&mut self, count: usize) -> io::Result<Vec<PageId>> {
+        let mut ids = Vec::with_capacity(count);
+        let mut freed_len = self.freed.len();
+        let mut free_head = self.free_head;
+        let mut page_count = self.page_count;
+        while ids.len() < count {
+            if freed_len > 0 {
+                freed_len -= 1;
+                ids.push(self.freed[freed_len]);
+            } else if free_head != HEADER {
+                ids.push(free_head);
+                // 空闲页的开头保存下一个空闲页的页号
+                free_head = PageId::decode(&mut &self.read_page(free_head)?[..])?;
+            } else {
+                ids.push(page_count);
+                page_count += 1;
+            }
+        }
+        self.freed.truncate(freed_len);
+        self.free_head = free_head;
+        self.page_count = page_count;
+        Ok(ids)
+    }
+    // 从缓存中移除并返回该页的节点，页号在 flush 时才写入空闲页链表
+    fn free(&mut self, id: PageId) -> Option<PageNode<K, V>> {
+        self.freed.push(id);
+        let cached = self.cache.remove(&id)?;
+        self.lru.remove(&cached.used);
+        Some(cached.node)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let dirty = self
+            .cache
+            .iter()
+            .filter(|(_, cached)| cached.dirty)
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        for id in dirty {
+            let bytes = self.cache[&id].node.encode(self.page_size)?;
+            self.write_page(id, &bytes)?;
+            self.cache.get_mut(&id).unwrap().dirty = false;
+        }
+        while let Some(&id) = self.freed.last() {
+            let mut bytes = vec![];
+            self.free_head.encode(&mut bytes);
+            bytes.resize(self.page_size, 0);
+            self.write_page(id, &bytes)?;
+            self.free_head = id;
+            self.freed.pop();
+        }
+        Ok(())
+    }
+}
+
+// 节点存放在单个文件的定长页中的 B 树，通过 LRU 缓存读写页。
+// 修改在页被淘汰以及 flush、close 时写入文件，drop 时也会 flush，但忽略错误
+pub struct PagedBTree<K: Ord + Codec, V: Codec> {
+    pager: Pager<K, V>,
+    root: PageId,
+    max_children_length: usize,
+    length: u64,
+}
+
+impl<K: Ord + Codec, V: Codec> PagedBTree<K, V> {
+    // 在 path 创建新文件，已有的文件会被清空
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        page_size: usize,
+        max_children_length: usize,
+        cache_capacity: usize,
+    ) -> io::Result<Self> {
+        assert!(max_children_length >= 4 && max_children_length <= u16::MAX as usize);
+        assert!(page_size >= HEADER_LEN && page_size > node_overhead(max_children_length));
+        assert!(cache_capacity >= 1);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut tree = Self {
+            pager: Pager::new(file, page_size, 1, HEADER, cache_capacity),
+            root: HEADER,
+            max_children_length,
+            length: 0,
+        };
+        tree.flush()?;
+        Ok(tree)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, cache_capacity: usize) -> io::Result<Self> {
+        assert!(cache_capacity >= 1);
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)?;
+        let bytes = &mut &header[..];
+        if take_bytes(bytes, MAGIC.len())? != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a paged B-tree file",
+            ));
+        }
+        let page_size = u32::decode(bytes)? as usize;
+        let max_children_length = u32::decode(bytes)? as usize;
+        if !(4..=u16::MAX as usize).contains(&max_children_length)
+            || page_size < HEADER_LEN
+            || page_size <= node_overhead(max_children_length)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid paged B-tree header",
+            ));
+        }
+        let root = PageId::decode(bytes)?;
+        let length = u64::decode(bytes)?;
+        let page_count = u64::decode(bytes)?;
+        let free_head = PageId::decode(bytes)?;
+        Ok(Self {
+            pager: Pager::new(file, page_size, page_count, free_head, cache_capacity),
+            root,
+            max_children_length,
+            length,
+        })
+    }
+
+    // 写回所有脏页和文件头
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.pager.flush()?;
+        let mut header = MAGIC.to_vec();
+        (self.pager.page_size as u32).encode(&mut header);
+        (self.max_children_length as u32).encode(&mut header);
+        self.root.encode(&mut header);
+        self.length.encode(&mut header);
+        self.pager.page_count.encode(&mut header);
+        self.pager.free_head.encode(&mut header);
+        header.resize(self.pager.page_size, 0);
+        self.pager.write_page(HEADER, &header)?;
+        self.pager.file.flush()
+    }
+
+    pub fn close(mut self) -> io::Result<()> {
+        self.flush()?;
+        self.pager.file.sync_all()
+    }
+
+    pub fn len(&self) -> usize {
+        self.length as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get(&mut self, key: &K) -> io::Result<Option<V>>
+    where
+        V: Clone,
+    {
+        let mut id = self.root;
+        while id != HEADER {
+            let node = self.pager.node(id)?;
+            match node.pairs.binary_search_by_key(&key, |(key, _value)| key) {
+                Ok(found) => return Ok(Some(node.pairs[found].1.clone())),
+                Err(child_index) => id = node.children.get(child_index).copied().unwrap_or(HEADER),
+            }
+        }
+        Ok(None)
+    }
+
+    // 每个键值对的编码长度都不超过该值时，不超过 max_children_length - 1 个键值对的节点
+    // 一定能放进一页，插入、分裂和合并都不会产生写不回去的节点
+    fn pair_len_limit(&self) -> usize {
+        (self.pager.page_size - node_overhead(self.max_children_length))
+            / (self.max_children_length - 1)
+    }
+
+    // 键值对太大、一页放不下 max_children_length - 1 个这样的键值对时返回 InvalidInput。
+    // 出错时树不会被修改
+    pub fn add(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        let mut bytes = vec![];
+        key.encode(&mut bytes);
+        value.encode(&mut bytes);
+        if bytes.len() > self.pair_len_limit() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pair does not fit in a page",
+            ));
+        }
+        self.pager.pin()?;
+        let result = self.add_pinned(key, value);
+        self.pager.unpin();
+        result
+    }
+    fn add_pinned(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        // 先读入查找路径上的节点
+        let mut path = vec![];
+        let mut id = self.root;
+        while id != HEADER {
+            let node = self.pager.node(id)?;
+            match node.pairs.binary_search_by_key(&&key, |(key, _value)| key) {
+                Ok(found) => {
+                    let node = self.pager.cached_mut(id);
+                    return Ok(Some(std::mem::replace(&mut node.pairs[found].1, value)));
+                }
+                Err(to_insert) => {
+                    path.push((id, to_insert));
+                    id = node.children.get(to_insert).copied().unwrap_or(HEADER);
+                }
+            }
+        }
+        // 从叶子节点向上连续的满节点都会分裂，根节点分裂或树为空时还需要一个新的根节点
+        let max_children_length = self.max_children_length;
+        let splits = path
+            .iter()
+            .rev()
+            .take_while(|&&(id, _)| self.pager.cached(id).pairs.len() + 1 >= max_children_length)
+            .count();
+        let mut new_ids = self.pager.alloc(splits + (splits == path.len()) as usize)?;
+
+        // 之后只修改缓存中的节点，不会再失败
+        let mut pair = (key, value);
+        let mut right_child = None;
+        for &(id, to_insert) in path.iter().rev() {
+            let node = self.pager.cached_mut(id);
+            match add_pair(node, max_children_length, to_insert, pair, right_child) {
+                None => {
+                    self.length += 1;
+                    return Ok(None);
+                }
+                Some((mid_pair, right)) => {
+                    let right_id = new_ids.pop().unwrap();
+                    self.pager.insert(right_id, right, true);
+                    pair = mid_pair;
+                    right_child = Some(right_id);
+                }
+            }
+        }
+        let root = new_ids.pop().unwrap();
+        let children = match right_child {
+            None => vec![],
+            Some(right) => vec![self.root, right],
+        };
+        self.pager
+            .insert(root, PageNode::from_parts(vec![pair], children), true);
+        self.root = root;
+        self.length += 1;
+        Ok(None)
+    }
+
+    // 出错时树不会被修改
+    pub fn remove(&mut self, key: &K) -> io::Result<Option<V>> {
+        self.pager.pin()?;
+        let result = self.remove_pinned(key);
+        self.pager.unpin();
+        result
+    }
+    fn remove_pinned(&mut self, key: &K) -> io::Result<Option<V>> {
+        if self.root == HEADER || !self.load_for_remove(key)? {
+            return Ok(None);
+        }
+        // 之后只修改缓存中的节点，不会再失败
+        let root = self.root;
+        let removed = self.remove_r(root, key);
+        self.length -= 1;
+        let node = self.pager.cached(root);
+        if node.pairs.is_empty() {
+            self.root = node.children.first().copied().unwrap_or(HEADER);
+            self.pager.free(root);
+        }
+        Ok(Some(removed))
+    }
+    // 读入删除时会用到的节点：查找路径、被删除的键在内部节点时它的前驱所在的路径，
+    // 以及路径上每个节点的左右兄弟。返回是否找到了 key
+    fn load_for_remove(&mut self, key: &K) -> io::Result<bool> {
+        let mut id = self.root;
+        let mut found = false;
+        loop {
+            let node = self.pager.node(id)?;
+            let child_index = if found {
+                node.pairs.len()
+            } else {
+                match node.pairs.binary_search_by_key(&key, |(key, _value)| key) {
+                    Ok(index) => {
+                        found = true;
+                        index
+                    }
+                    Err(index) => index,
+                }
+            };
+            if node.children.is_empty() {
+                return Ok(found);
+            }
+            let siblings = [child_index.checked_sub(1), Some(child_index + 1)]
+                .iter()
+                .filter_map(|&index| node.children.get(index?).copied())
+                .collect::<Vec<_>>();
+            id = node.children[child_index];
+            for sibling in siblings {
+                self.pager.node(sibling)?;
+            }
+        }
+    }
+    fn remove_r(&mut self, id: PageId, key: &K) -> V {
+        let node = self.pager.cached(id);
+        match node.pairs.binary_search_by_key(&key, |(key, _value)| key) {
+            Ok(found) => match node.children.get(found).copied() {
+                None => {
+                    // 未获取到child，则说明node为叶子节点，则执行删除
+                    self.pager.cached_mut(id).pairs.remove(found).1
+                }
+                Some(child) => {
+                    let pre = self.remove_right_most_r(child);
+                    let node = self.pager.cached_mut(id);
+                    let (_, value) = std::mem::replace(&mut node.pairs[found], pre);
+                    self.fix_on_child_removed(id, found);
+                    value
+                }
+            },
+            Err(child_index) => {
+                let child = node.children[child_index];
+                let removed = self.remove_r(child, key);
+                self.fix_on_child_removed(id, child_index);
+                removed
+            }
+        }
+    }
+    fn remove_right_most_r(&mut self, id: PageId) -> Pair<K, V> {
+        let node = self.pager.cached(id);
+        match node.children.last().copied() {
+            None => self.pager.cached_mut(id).pairs.pop().unwrap(),
+            Some(last_child) => {
+                let last_index = node.children.len() - 1;
+                let removed = self.remove_right_most_r(last_child);
+                self.fix_on_child_removed(id, last_index);
+                removed
+            }
+        }
+    }
+    fn fix_on_child_removed(&mut self, id: PageId, child_index: usize) {
+        let min_pairs_len = self.max_children_length / 2 - 1;
+        let node = self.pager.cached(id);
+        let left = child_index.checked_sub(1).map(|index| node.children[index]);
+        let child = node.children[child_index];
+        let right = node.children.get(child_index + 1).copied();
+        let child_len = self.pager.cached(child).pairs.len();
+        let left_len = left.map(|left| self.pager.cached(left).pairs.len());
+        let right_len = right.map(|right| self.pager.cached(right).pairs.len());
+
+        match plan_rebalance(min_pairs_len, child_index, child_len, left_len, right_len) {
+            Rebalance::None => {}
+            Rebalance::BorrowLeft => {
+                let [parent, left_node, child_node] =
+                    self.pager.cached_disjoint_mut([id, left.unwrap(), child]);
+                borrow_from_left(&mut parent.pairs[child_index - 1], left_node, child_node);
+            }
+            Rebalance::BorrowRight => {
+                let [parent, child_node, right_node] =
+                    self.pager.cached_disjoint_mut([id, child, right.unwrap()]);
+                borrow_from_right(&mut parent.pairs[child_index], child_node, right_node);
+            }
+            Rebalance::Merge(left_index) => {
+                let parent = self.pager.cached_mut(id);
+                let separator = parent.pairs.remove(left_index);
+                let right = parent.children.remove(left_index + 1);
+                let left = parent.children[left_index];
+                let right_node = self.pager.free(right).unwrap();
+                merge(separator, self.pager.cached_mut(left), right_node);
+            }
+        }
+    }
+
+    pub fn is_valid(&mut self) -> io::Result<bool>
+    where
+        K: Clone,
+    {
+        if self.root == HEADER {
+            return Ok(self.length == 0);
+        }
+        let mut leaf_depth = None;
+        let count = self.validate_node(self.root, None, None, 0, &mut leaf_depth)?;
+        Ok(count == Some(self.length))
+    }
+    // 返回子树中键值对的个数，不满足 B 树的性质时返回 None
+    fn validate_node(
+        &mut self,
+        id: PageId,
+        min: Option<&K>,
+        max: Option<&K>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+    ) -> io::Result<Option<u64>>
+    where
+        K: Clone,
+    {
+        let max_children_length = self.max_children_length;
+        let node = self.pager.node(id)?;
+        let min_pairs_len = if depth == 0 {
+            1
+        } else {
+            max_children_length / 2 - 1
+        };
+        if node.pairs.len() < min_pairs_len
+            || node.pairs.len() >= max_children_length
+            || (!node.children.is_empty() && node.children.len() != node.pairs.len() + 1)
+        {
+            return Ok(None);
+        }
+        let keys = node
+            .pairs
+            .iter()
+            .map(|(key, _value)| key.clone())
+            .collect::<Vec<_>>();
+        let children = node.children.clone();
+        if keys.windows(2).any(|keys| keys[0] >= keys[1])
+            || min.is_some_and(|min| keys[0] <= *min)
+            || max.is_some_and(|max| *keys.last().unwrap() >= *max)
+        {
+            return Ok(None);
+        }
+
+        if children.is_empty() {
+            if *leaf_depth.get_or_insert(depth) != depth {
+                return Ok(None);
+            }
+            return Ok(Some(keys.len() as u64));
+        }
+        let mut count = keys.len() as u64;
+        for (i, &child) in children.iter().enumerate() {
+            let child_min = i.checked_sub(1).map_or(min, |i| Some(&keys[i]));
+            let child_max = keys.get(i).map_or(max, Some);
+            match self.validate_node(child, child_min, child_max, depth + 1, leaf_depth)? {
+                None => return Ok(None),
+                Some(child_count) => count += child_count,
+            }
+        }
+        Ok(Some(count))
+    }
+}
+
+impl<K: Ord + Codec, V: Codec> Drop for PagedBTree<K, V> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use rand::Rng;
+
+    use crate::paged_b_tree::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("paged_b_tree_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn add_and_remove() -> io::Result<()> {
+        let path = temp_path("add_and_remove");
+        let mut rng = rand::thread_rng();
+        let mut tree = PagedBTree::create(&path, 256, 5, 4)?;
+        let mut std_tree = BTreeMap::new();
+        for i in 0..3000 {
+            let key = rng.gen_range(0..500u32);
+            if rng.gen_bool(0.4) {
+                assert_eq!(tree.remove(&key)?, std_tree.remove(&key));
+            } else {
+                let value = format!("{}", key * 2);
+                assert_eq!(tree.add(key, value.clone())?, std_tree.insert(key, value));
+            }
+            assert_eq!(tree.get(&key)?.as_ref(), std_tree.get(&key));
+            assert_eq!(tree.len(), std_tree.len());
+            if i % 100 == 0 {
+                assert!(tree.is_valid()?);
+            }
+        }
+        tree.close()?;
+
+        let mut tree = PagedBTree::<u32, String>::open(&path, 2)?;
+        assert!(tree.is_valid()?);
+        assert_eq!(tree.len(), std_tree.len());
+        for key in 0..500 {
+            assert_eq!(tree.get(&key)?.as_ref(), std_tree.get(&key));
+        }
+
+        // 删除后释放的页会被重新使用
+        let page_count = tree.pager.page_count;
+        for key in 0..500 {
+            tree.remove(&key)?;
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.root, HEADER);
+        // 少量键值对所需的页少于被释放的页
+        for (key, value) in std_tree.iter().take(20) {
+            tree.add(*key, value.clone())?;
+        }
+        assert_eq!(tree.pager.page_count, page_count);
+        for (key, value) in std_tree {
+            tree.add(key, value)?;
+        }
+        assert!(tree.is_valid()?);
+        tree.close()?;
+        std::fs::remove_file(&path)
+    }
+
+    #[test]
+    fn io_failure() -> io::Result<()> {
+        let path = temp_path("io_failure");
+        let mut rng = rand::thread_rng();
+        // 缓存很小，分裂与合并时大多数节点都要从文件中读入，被淘汰的脏节点要写回
+        let mut tree = PagedBTree::create(&path, 256, 4, 2)?;
+        let mut std_tree = BTreeMap::new();
+        let mut failures = 0;
+        for i in 0..3000 {
+            let key = rng.gen_range(0..300u32);
+            let value = format!("{}", i);
+            let remove = rng.gen_bool(0.4);
+            // 在操作中途的某次读写页时出错
+            tree.pager.fail_after = Some(rng.gen_range(0..6));
+            let result = if remove {
+                tree.remove(&key)
+            } else {
+                tree.add(key, value.clone())
+            };
+            tree.pager.fail_after = None;
+            match result {
+                Ok(old_value) if remove => assert_eq!(old_value, std_tree.remove(&key)),
+                Ok(old_value) => assert_eq!(old_value, std_tree.insert(key, value)),
+                // 出错时树保持原样
+                Err(error) => {
+                    assert_eq!(error.kind(), io::ErrorKind::Other);
+                    assert!(tree.is_valid()?);
+                    assert_eq!(tree.get(&key)?.as_ref(), std_tree.get(&key));
+                    failures += 1;
+                }
+            }
+            assert_eq!(tree.len(), std_tree.len());
+        }
+        assert!(failures > 0 && failures < 3000);
+        assert!(tree.is_valid()?);
+        tree.close()?;
+
+        let mut tree = PagedBTree::<u32, String>::open(&path, 2)?;
+        assert!(tree.is_valid()?);
+        for key in 0..300 {
+            assert_eq!(tree.get(&key)?.as_ref(), std_tree.get(&key));
+        }
+        tree.close()?;
+        std::fs::remove_file(&path)
+    }
+
+    #[test]
+    fn page_overflow() -> io::Result<()> {
+        let path = temp_path("page_overflow");
+        // 每个键值对最多 (128 - 36) / 3 = 30 字节
+        let mut tree = PagedBTree::create(&path, 128, 4, 1)?;
+        for key in 0..20i64 {
+            tree.add(key, "x".repeat(18))?;
+        }
+        assert_eq!(
+            tree.add(20, "x".repeat(19)).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            tree.add(0, "x".repeat(100)).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(tree.len(), 20);
+        assert_eq!(tree.get(&0)?, Some("x".repeat(18)));
+        assert_eq!(tree.get(&20)?, None);
+        assert!(tree.is_valid()?);
+        tree.close()?;
+
+        std::fs::write(&path, [0; HEADER_LEN])?;
+        assert!(PagedBTree::<i64, String>::open(&path, 8).is_err());
+
+        // 文件头中的 page_size 小于文件头本身
+        let mut header = MAGIC.to_vec();
+        16u32.encode(&mut header);
+        4u32.encode(&mut header);
+        header.resize(HEADER_LEN, 0);
+        std::fs::write(&path, &header)?;
+        assert_eq!(
+            PagedBTree::<i64, String>::open(&path, 8)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // max_children_length 小于 4
+        let mut header = MAGIC.to_vec();
+        4096u32.encode(&mut header);
+        3u32.encode(&mut header);
+        header.resize(HEADER_LEN, 0);
+        std::fs::write(&path, &header)?;
+        assert_eq!(
+            PagedBTree::<i64, String>::open(&path, 8)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        std::fs::remove_file(&path)
+    }
+}
@@ -0,0 +1,486 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::persistent_red_black::Color::{Black, Red};
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Color {
+    Red,
+    Black,
+}
+
+type Tree<K, V> = Option<Rc<Node<K, V>>>;
+// 键值对单独放在 Rc 里，复制路径上的节点时不需要 clone 键和值
+type Pair<K, V> = Rc<(K, V)>;
+
+struct Node<K: Ord, V> {
+    color: Color,
+    pair: Pair<K, V>,
+    left: Tree<K, V>,
+    right: Tree<K, V>,
+}
+
+fn new<K: Ord, V>(
+    color: Color,
+    left: Tree<K, V>,
+    pair: Pair<K, V>,
+    right: Tree<K, V>,
+) -> Tree<K, V> {
+    Some(Rc::new(Node {
+        color,
+        pair,
+        left,
+        right,
+    }))
+}
+
+fn paint<K: Ord, V>(node: &Rc<Node<K, V>>, color: Color) -> Tree<K, V> {
+    if node.color == color {
+        Some(node.clone())
+    } else {
+        new(
+            color,
+            node.left.clone(),
+            node.pair.clone(),
+            node.right.clone(),
+        )
+    }
+}
+
+fn red<K: Ord, V>(tree: &Tree<K, V>) -> Option<&Rc<Node<K, V>>> {
+    tree.as_ref().filter(|node| node.color == Red)
+}
+
+fn black<K: Ord, V>(tree: &Tree<K, V>) -> Option<&Rc<Node<K, V>>> {
+    tree.as_ref().filter(|node| node.color == Black)
+}
+
+// 消除子节点与孙节点之间的红红冲突，没有冲突时返回黑色节点
+fn balance<K: Ord, V>(left: Tree<K, V>, pair: Pair<K, V>, right: Tree<K, V>) -> Tree<K, V> {
+    if let (Some(l), Some(r)) = (red(&left), red(&right)) {
+        return new(Red, paint(l, Black), pair, paint(r, Black));
+    }
+    if let Some(l) = red(&left) {
+        if let Some(ll) = red(&l.left) {
+            return new(
+                Red,
+                paint(ll, Black),
+                l.pair.clone(),
+                new(Black, l.right.clone(), pair, right),
+            );
+        }
+        if let Some(lr) = red(&l.right) {
+            return new(
+                Red,
+                new(Black, l.left.clone(), l.pair.clone(), lr.left.clone()),
+                lr.pair.clone(),
+                new(Black, lr.right.clone(), pair, right),
+            );
+        }
+    }
+    if let Some(r) = red(&right) {
+        if let Some(rr) = red(&r.right) {
+            return new(
+                Red,
+                new(Black, left, pair, r.left.clone()),
+                r.pair.clone(),
+                paint(rr, Black),
+            );
+        }
+        if let Some(rl) = red(&r.left) {
+            return new(
+                Red,
+                new(Black, left, pair, rl.left.clone()),
+                rl.pair.clone(),
+                new(Black, rl.right.clone(), r.pair.clone(), r.right.clone()),
+            );
+        }
+    }
+    new(Black, left, pair, right)
+}
+
+// 左子树的黑高比右子树少 1
+fn balance_left<K: Ord, V>(left: Tree<K, V>, pair: Pair<K, V>, right: Tree<K, V>) -> Tree<K, V> {
+    if let Some(l) = red(&left) {
+        return new(Red, paint(l, Black), pair, right);
+    }
+    if let Some(r) = black(&right) {
+        return balance(left, pair, paint(r, Red));
+    }
+    let r = red(&right).unwrap();
+    let rl = black(&r.left).unwrap();
+    let rr = black(&r.right).unwrap();
+    new(
+        Red,
+        new(Black, left, pair, rl.left.clone()),
+        rl.pair.clone(),
+        balance(rl.right.clone(), r.pair.clone(), paint(rr, Red)),
+    )
+}
+
+// 右子树的黑高比左子树少 1
+fn balance_right<K: Ord, V>(left: Tree<K, V>, pair: Pair<K, V>, right: Tree<K, V>) -> Tree<K, V> {
+    if let Some(r) = red(&right) {
+        return new(Red, left, pair, paint(r, Black));
+    }
+    if let Some(l) = black(&left) {
+        return balance(paint(l, Red), pair, right);
+    }
+    let l = red(&left).unwrap();
+    let ll = black(&l.left).unwrap();
+    let lr = black(&l.right).unwrap();
+    new(
+        Red,
+        balance(paint(ll, Red), l.pair.clone(), lr.left.clone()),
+        lr.pair.clone(),
+        new(Black, lr.right.clone(), pair, right),
+    )
+}
+
+// 合并被删除节点的左右子树
+fn fuse<K: Ord, V>(left: &Tree<K, V>, right: &Tree<K, V>) -> Tree<K, V> {
+    let (l, r) = match (left, right) {
+        (None, tree) | (tree, None) => return tree.clone(),
+        (Some(l), Some(r)) => (l, r),
+    };
+    match (l.color, r.color) {
+        (Black, Red) => new(Red, fuse(left, &r.left), r.pair.clone(), r.right.clone()),
+        (Red, Black) => new(Red, l.left.clone(), l.pair.clone(), fuse(&l.right, right)),
+        (Red, Red) => {
+            let middle = fuse(&l.right, &r.left);
+            match red(&middle) {
+                Some(m) => new(
+                    Red,
+                    new(Red, l.left.clone(), l.pair.clone(), m.left.clone()),
+                    m.pair.clone(),
+                    new(Red, m.right.clone(), r.pair.clone(), r.right.clone()),
+                ),
+                None => new(
+                    Red,
+                    l.left.clone(),
+                    l.pair.clone(),
+                    new(Red, middle, r.pair.clone(), r.right.clone()),
+                ),
+            }
+        }
+        (Black, Black) => {
+            let middle = fuse(&l.right, &r.left);
+            match red(&middle) {
+                Some(m) => new(
+                    Red,
+                    new(Black, l.left.clone(), l.pair.clone(), m.left.clone()),
+                    m.pair.clone(),
+                    new(Black, m.right.clone(), r.pair.clone(), r.right.clone()),
+                ),
+                None => balance_left(
+                    l.left.clone(),
+                    l.pair.clone(),
+                    new(Black, middle, r.pair.clone(), r.right.clone()),
+                ),
+            }
+        }
+    }
+}
+
+// 不可变红黑树，insert 和 remove 返回共享未修改子树的新版本
+pub struct PersistentRedBlackTree<K: Ord, V> {
+    root: Tree<K, V>,
+    length: usize,
+}
+
+impl<K: Ord, V> Default for PersistentRedBlackTree<K, V> {
+    fn default() -> Self {
+        Self {
+            root: None,
+            length: 0,
+        }
+    }
+}
+
+impl<K: Ord, V> Clone for PersistentRedBlackTree<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            length: self.length,
+        }
+    }
+}
+
+impl<K: Ord, V> PersistentRedBlackTree<K, V> {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        if red(&self.root).is_some() {
+            return false;
+        }
+        match Self::validate(&self.root, None, None) {
+            Ok((_black_height, count)) => count == self.length,
+            Err(()) => false,
+        }
+    }
+    // 返回子树的黑高和节点数
+    fn validate(tree: &Tree<K, V>, min: Option<&K>, max: Option<&K>) -> Result<(usize, usize), ()> {
+        match tree {
+            None => Ok((1, 0)),
+            Some(node) => {
+                let key = &node.pair.0;
+                if min.is_some_and(|min| key <= min) || max.is_some_and(|max| key >= max) {
+                    return Err(());
+                }
+                if node.color == Red && (red(&node.left).is_some() || red(&node.right).is_some()) {
+                    return Err(());
+                }
+                let (left_height, left_count) = Self::validate(&node.left, min, Some(key))?;
+                let (right_height, right_count) = Self::validate(&node.right, Some(key), max)?;
+                if left_height != right_height {
+                    return Err(());
+                }
+                let black_height = match node.color {
+                    Red => left_height,
+                    Black => left_height + 1,
+                };
+                Ok((black_height, left_count + right_count + 1))
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut tree = &self.root;
+        while let Some(node) = tree {
+            tree = match key.cmp(&node.pair.0) {
+                Ordering::Less => &node.left,
+                Ordering::Equal => return Some(&node.pair.1),
+                Ordering::Greater => &node.right,
+            };
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let (root, replaced) = Self::insert_rec(&self.root, key, value);
+        Self {
+            root: paint(root.as_ref().unwrap(), Black),
+            length: if replaced {
+                self.length
+            } else {
+                self.length + 1
+            },
+        }
+    }
+    fn insert_rec(tree: &Tree<K, V>, key: K, value: V) -> (Tree<K, V>, bool) {
+        let node = match tree {
+            None => return (new(Red, None, Rc::new((key, value)), None), false),
+            Some(node) => node,
+        };
+        match key.cmp(&node.pair.0) {
+            Ordering::Less => {
+                let (left, replaced) = Self::insert_rec(&node.left, key, value);
+                let tree = match node.color {
+                    Red => new(Red, left, node.pair.clone(), node.right.clone()),
+                    Black => balance(left, node.pair.clone(), node.right.clone()),
+                };
+                (tree, replaced)
+            }
+            Ordering::Equal => {
+                let pair = Rc::new((key, value));
+                let tree = new(node.color, node.left.clone(), pair, node.right.clone());
+                (tree, true)
+            }
+            Ordering::Greater => {
+                let (right, replaced) = Self::insert_rec(&node.right, key, value);
+                let tree = match node.color {
+                    Red => new(Red, node.left.clone(), node.pair.clone(), right),
+                    Black => balance(node.left.clone(), node.pair.clone(), right),
+                };
+                (tree, replaced)
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &K) -> Self {
+        // 只在键存在时修改，删除过程依赖于此
+        if !self.contains_key(key) {
+            return self.clone();
+        }
+        let root = Self::remove_rec(&self.root, key);
+        Self {
+            root: root.as_ref().and_then(|root| paint(root, Black)),
+            length: self.length - 1,
+        }
+    }
+    // 删除黑色节点后子树黑高减 1，删除红色节点后黑高不变
+    fn remove_rec(tree: &Tree<K, V>, key: &K) -> Tree<K, V> {
+        let node = tree.as_ref().unwrap();
+        match key.cmp(&node.pair.0) {
+            Ordering::Less => {
+                let left = Self::remove_rec(&node.left, key);
+                match black(&node.left) {
+                    Some(_) => balance_left(left, node.pair.clone(), node.right.clone()),
+                    None => new(Red, left, node.pair.clone(), node.right.clone()),
+                }
+            }
+            Ordering::Equal => fuse(&node.left, &node.right),
+            Ordering::Greater => {
+                let right = Self::remove_rec(&node.right, key);
+                match black(&node.right) {
+                    Some(_) => balance_right(node.left.clone(), node.pair.clone(), right),
+                    None => new(Red, node.left.clone(), node.pair.clone(), right),
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter {
+            stack: vec![],
+            remain_len: self.length,
+        };
+        iter.push_left(&self.root);
+        iter
+    }
+}
+
+pub struct Iter<'t, K: Ord, V> {
+    stack: Vec<&'t Node<K, V>>,
+    remain_len: usize,
+}
+
+impl<'t, K: Ord, V> Iter<'t, K, V> {
+    fn push_left(&mut self, mut tree: &'t Tree<K, V>) {
+        while let Some(node) = tree {
+            self.stack.push(node);
+            tree = &node.left;
+        }
+    }
+}
+
+impl<'t, K: Ord, V> Iterator for Iter<'t, K, V> {
+    type Item = (&'t K, &'t V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.remain_len -= 1;
+        self.push_left(&node.right);
+        Some((&node.pair.0, &node.pair.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remain_len, Some(self.remain_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    use rand::prelude::SliceRandom;
+    use rand::Rng;
+
+    use crate::persistent_red_black::*;
+
+    fn height(tree: &Tree<i32, i32>) -> usize {
+        tree.as_ref().map_or(0, |node| {
+            std::cmp::max(height(&node.left), height(&node.right)) + 1
+        })
+    }
+
+    fn to_vec(tree: &PersistentRedBlackTree<i32, i32>) -> Vec<(i32, i32)> {
+        tree.iter().map(|(&k, &v)| (k, v)).collect()
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        const NUM: i32 = 1000;
+        let mut tree = PersistentRedBlackTree::default();
+        let mut std_tree = BTreeMap::new();
+        let mut elements: Vec<i32> = (0..NUM).collect();
+        let mut rng = rand::thread_rng();
+        elements.shuffle(&mut rng);
+        elements.iter().for_each(|&element| {
+            tree = tree.insert(element, element);
+            std_tree.insert(element, element);
+            assert!(tree.is_valid());
+            assert_eq!(tree.len(), std_tree.len());
+        });
+        tree = tree.insert(99, -99);
+        std_tree.insert(99, -99);
+        assert_eq!(tree.len(), NUM as usize);
+        assert_eq!(tree.get(&99), Some(&-99));
+        // 高度不超过 2 * log2(n + 1)
+        assert!(height(&tree.root) <= 20);
+
+        while !tree.is_empty() {
+            let to_remove = rng.gen_range(0..NUM);
+            tree = tree.remove(&to_remove);
+            std_tree.remove(&to_remove);
+            assert!(!tree.contains_key(&to_remove));
+            assert!(tree.is_valid());
+            assert_eq!(tree.len(), std_tree.len());
+        }
+        assert!(std_tree.is_empty());
+    }
+
+    #[test]
+    fn versions() {
+        let mut rng = rand::thread_rng();
+        let mut versions = vec![PersistentRedBlackTree::default()];
+        let mut std_versions = vec![BTreeMap::new()];
+        for _ in 0..2000 {
+            let base = rng.gen_range(0..versions.len());
+            let key = rng.gen_range(0..200);
+            let (tree, mut std_tree) = (&versions[base], std_versions[base].clone());
+            let tree = if rng.gen_bool(0.3) {
+                std_tree.remove(&key);
+                tree.remove(&key)
+            } else {
+                std_tree.insert(key, versions.len() as i32);
+                tree.insert(key, versions.len() as i32)
+            };
+            versions.push(tree);
+            std_versions.push(std_tree);
+        }
+        for (tree, std_tree) in versions.iter().zip(std_versions.iter()) {
+            assert!(tree.is_valid());
+            assert_eq!(
+                to_vec(tree),
+                std_tree.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn structural_sharing() {
+        let mut tree = PersistentRedBlackTree::default();
+        for i in 0..1024 {
+            tree = tree.insert(i, i);
+        }
+        let old = tree.clone();
+        let new = tree.insert(2000, 2000);
+        // 插入最大的键只会复制最右侧的路径，左子树完全共享
+        let (old_root, new_root) = (old.root.as_ref().unwrap(), new.root.as_ref().unwrap());
+        assert!(Rc::ptr_eq(
+            old_root.left.as_ref().unwrap(),
+            new_root.left.as_ref().unwrap()
+        ));
+        assert_eq!(old.len(), 1024);
+        assert!(!old.contains_key(&2000));
+        assert_eq!(new.len(), 1025);
+
+        let removed = new.remove(&5000);
+        assert!(Rc::ptr_eq(removed.root.as_ref().unwrap(), new_root));
+        let removed = new.remove(&0);
+        assert_eq!(to_vec(&removed)[0], (1, 1));
+        assert_eq!(to_vec(&new)[0], (0, 0));
+    }
+}
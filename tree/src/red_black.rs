@@ -1,17 +1,16 @@
-use std::borrow::Borrow;
-use std::cell::RefCell;
-use std::cmp;
+use std::cell::UnsafeCell;
 use std::cmp::Ordering;
-use std::marker::PhantomData;
-use std::ops::{Deref, DerefMut};
-use std::option::Option::Some;
-use std::rc::{Rc, Weak};
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{self, AtomicBool, AtomicU32};
+use std::sync::{Arc, Mutex, OnceLock};
 
+use crate::invariant::InvariantViolation;
 use crate::red_black::Color::{Black, Red};
 
-type NodeRef<K, V> = RefCell<Node<K, V>>;
-type RcNodeRef<K, V> = Rc<NodeRef<K, V>>;
-type WeakNodeRef<K, V> = Weak<NodeRef<K, V>>;
+type Index = u32;
+
+// 空子节点或根节点的父节点
+const NIL: Index = Index::MAX;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum Color {
@@ -19,469 +18,1372 @@ enum Color {
     Black,
 }
 
-pub struct Node<K: Ord, V> {
+struct Node<K: Ord, V> {
     key: K,
     value: V,
     color: Color,
-    parent: Option<WeakNodeRef<K, V>>,
-    left: Option<RcNodeRef<K, V>>,
-    right: Option<RcNodeRef<K, V>>,
+    parent: Index,
+    left: Index,
+    right: Index,
+    // 子树中的节点数
+    size: Index,
+}
+
+enum Slot<K: Ord, V> {
+    Occupied(Node<K, V>),
+    // 空闲槽位组成链表，记录下一个空闲槽位
+    Free(Index),
+}
+
+// 槽位分块分配，第 c 块有 CHUNK_BASE << c 个槽位。块分配后不再移动，
+// 因此共用存储的其他树插入节点时，本树交出的引用仍然有效
+const CHUNK_BASE: usize = 64;
+const CHUNKS: usize = (Index::BITS + 1 - CHUNK_BASE.trailing_zeros()) as usize;
+
+type Chunk<K, V> = Box<[UnsafeCell<Slot<K, V>>]>;
+
+fn locate(index: Index) -> (usize, usize) {
+    let position = index as u64 + CHUNK_BASE as u64;
+    let chunk = (u64::BITS - 1 - position.leading_zeros() - CHUNK_BASE.trailing_zeros()) as usize;
+    (chunk, (position - ((CHUNK_BASE as u64) << chunk)) as usize)
+}
+
+// 多棵树可以共用一个节点存储，split_off 得到的树和 new_in 创建的树与原来的树共用，
+// 它们之间的 join、split_off 和 append 只需要重新链接节点
+struct Store<K: Ord, V> {
+    chunks: [OnceLock<Chunk<K, V>>; CHUNKS],
+    // 第一个从未使用过的槽位
+    next: AtomicU32,
+    // 被删除的树交还的空闲槽位链表的头和尾
+    recycled: Mutex<(Index, Index)>,
+    has_recycled: AtomicBool,
+}
+
+// 被占用的槽位只属于一棵树，只通过这棵树访问；空闲槽位只由持有它的树访问，或在加锁后交还
+unsafe impl<K: Ord + Send, V: Send> Send for Store<K, V> {}
+unsafe impl<K: Ord + Send + Sync, V: Send + Sync> Sync for Store<K, V> {}
+
+impl<K: Ord, V> Store<K, V> {
+    fn new() -> Self {
+        Self {
+            chunks: std::array::from_fn(|_| OnceLock::new()),
+            next: AtomicU32::new(0),
+            recycled: Mutex::new((NIL, NIL)),
+            has_recycled: AtomicBool::new(false),
+        }
+    }
+    fn slot(&self, index: Index) -> *mut Slot<K, V> {
+        let (chunk, offset) = locate(index);
+        self.chunks[chunk].get().unwrap()[offset].get()
+    }
+    fn fresh(&self) -> Index {
+        let index = self.next.fetch_add(1, atomic::Ordering::Relaxed);
+        assert!(index < NIL);
+        let (chunk, _) = locate(index);
+        self.chunks[chunk].get_or_init(|| {
+            (0..CHUNK_BASE << chunk)
+                .map(|_| UnsafeCell::new(Slot::Free(NIL)))
+                .collect()
+        });
+        index
+    }
+    // 取走其他树交还的全部空闲槽位
+    fn take_recycled(&self) -> (Index, Index) {
+        if !self.has_recycled.load(atomic::Ordering::Relaxed) {
+            return (NIL, NIL);
+        }
+        let mut recycled = self.recycled.lock().unwrap();
+        self.has_recycled.store(false, atomic::Ordering::Relaxed);
+        std::mem::replace(&mut recycled, (NIL, NIL))
+    }
 }
 
-#[derive(Default)]
 pub struct RedBlackTree<K: Ord, V> {
-    root: Option<RcNodeRef<K, V>>,
-    length: usize,
+    store: Arc<Store<K, V>>,
+    // 本树释放的槽位组成链表，记录头和尾
+    free_head: Index,
+    free_tail: Index,
+    root: Index,
 }
 
-struct ValidationResult {
-    red_red_count: usize,
-    black_height_min: usize,
-    black_height_max: usize,
+impl<K: Ord, V> Default for RedBlackTree<K, V> {
+    fn default() -> Self {
+        Self {
+            store: Arc::new(Store::new()),
+            free_head: NIL,
+            free_tail: NIL,
+            root: NIL,
+        }
+    }
 }
 
-impl ValidationResult {
-    fn is_valid(&self) -> bool {
-        self.red_red_count == 0 && self.black_height_min == self.black_height_max
+// 其他树仍在使用存储时，释放本树的节点，并把空闲槽位交还给存储
+impl<K: Ord, V> Drop for RedBlackTree<K, V> {
+    fn drop(&mut self) {
+        if Arc::get_mut(&mut self.store).is_some() {
+            return;
+        }
+        self.discard_subtree(self.root);
+        self.root = NIL;
+        if self.free_head == NIL {
+            return;
+        }
+        let mut recycled = self.store.recycled.lock().unwrap();
+        if recycled.0 == NIL {
+            recycled.1 = self.free_tail;
+        } else {
+            unsafe { *self.store.slot(self.free_tail) = Slot::Free(recycled.0) };
+        }
+        recycled.0 = self.free_head;
+        self.store
+            .has_recycled
+            .store(true, atomic::Ordering::Relaxed);
     }
 }
 
+// 同一个存储中一棵独立的子树，根节点为黑色且没有父节点
+#[derive(Copy, Clone)]
+struct Part {
+    root: Index,
+    black_height: usize,
+}
+
+const EMPTY: Part = Part {
+    root: NIL,
+    black_height: 0,
+};
+
 impl<K: Ord, V> RedBlackTree<K, V> {
+    // 创建与 other 共用节点存储的空树
+    pub fn new_in(other: &Self) -> Self {
+        Self {
+            store: other.store.clone(),
+            free_head: NIL,
+            free_tail: NIL,
+            root: NIL,
+        }
+    }
+
     pub fn length(&self) -> usize {
-        self.length
+        self.size(self.root) as usize
     }
 
     pub fn is_valid(&self) -> bool {
-        Self::validate(&self.root, Color::Red).is_valid()
-    }
-    fn validate(tree: &Option<RcNodeRef<K, V>>, parent_color: Color) -> ValidationResult {
-        match tree {
-            None => ValidationResult {
-                red_red_count: 0,
-                black_height_min: 1,
-                black_height_max: 1,
-            },
-            Some(tree) => {
-                let tree = tree.deref().borrow();
-                let left_result = Self::validate(&tree.left, tree.color);
-                let right_result = Self::validate(&tree.right, tree.color);
-                let (self_red_red_count, self_black_count) = match tree.color {
-                    Red => match parent_color {
-                        Red => (1, 0),
-                        Black => (0, 0),
-                    },
-                    Black => (0, 1),
-                };
-
-                ValidationResult {
-                    red_red_count: left_result.red_red_count
-                        + right_result.red_red_count
-                        + self_red_red_count,
-                    black_height_min: self_black_count
-                        + cmp::min(left_result.black_height_min, right_result.black_height_min),
-                    black_height_max: self_black_count
-                        + cmp::min(left_result.black_height_max, right_result.black_height_max),
+        self.validate().is_ok()
+    }
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        if self.color(self.root) == Red {
+            return Err(InvariantViolation::RedRoot);
+        }
+        if self.root != NIL && self.node(self.root).parent != NIL {
+            return Err(InvariantViolation::ParentLink { path: vec![] });
+        }
+        self.validate_node(self.root, None, None, &mut vec![])?;
+        Ok(())
+    }
+    // 返回子树的黑高和节点数，path 为从根节点到 index 的路径，0 为左 1 为右
+    fn validate_node(
+        &self,
+        index: Index,
+        min: Option<&K>,
+        max: Option<&K>,
+        path: &mut Vec<usize>,
+    ) -> Result<(usize, usize), InvariantViolation> {
+        if index == NIL {
+            return Ok((0, 0));
+        }
+        let node = self.node(index);
+        if min.is_some_and(|min| node.key <= *min) || max.is_some_and(|max| node.key >= *max) {
+            return Err(InvariantViolation::KeyOrder { path: path.clone() });
+        }
+        let mut child_results = [(0, 0); 2];
+        for (side, &child) in [node.left, node.right].iter().enumerate() {
+            path.push(side);
+            if child != NIL {
+                if self.node(child).parent != index {
+                    return Err(InvariantViolation::ParentLink { path: path.clone() });
+                }
+                if node.color == Red && self.color(child) == Red {
+                    return Err(InvariantViolation::RedRed { path: path.clone() });
                 }
             }
+            let (min, max) = if side == 0 {
+                (min, Some(&node.key))
+            } else {
+                (Some(&node.key), max)
+            };
+            child_results[side] = self.validate_node(child, min, max, path)?;
+            path.pop();
+        }
+
+        let [(left_height, left_count), (right_height, right_count)] = child_results;
+        let count = left_count + right_count + 1;
+        if node.size as usize != count {
+            return Err(InvariantViolation::Length {
+                recorded: node.size as usize,
+                counted: count,
+            });
         }
+        if left_height != right_height {
+            return Err(InvariantViolation::BlackHeight {
+                path: path.clone(),
+                left: left_height,
+                right: right_height,
+            });
+        }
+        let self_black_count = match node.color {
+            Red => 0,
+            Black => 1,
+        };
+        Ok((left_height + self_black_count, count))
     }
 
-    fn add_rec(
-        tree: Option<Rc<NodeRef<K, V>>>,
-        parent: Option<Weak<NodeRef<K, V>>>,
-        key: K,
-        value: V,
-    ) -> (RcNodeRef<K, V>, Result<RcNodeRef<K, V>, V>) {
-        match tree {
-            None => {
-                let new_node = Rc::new(RefCell::new(Node {
-                    key,
-                    value,
-                    color: Red,
-                    parent,
-                    left: None,
-                    right: None,
-                }));
-                (new_node.clone(), Ok(new_node))
-            }
-            Some(node_rc) => {
-                let weak = Rc::downgrade(&node_rc);
-                let mut node = node_rc.borrow_mut();
-                let new_node = match key.cmp(&node.key) {
-                    Ordering::Less => {
-                        let (left_root, new_node) =
-                            Self::add_rec(node.left.take(), Some(weak), key, value);
-                        node.left = Some(left_root);
-                        new_node
-                    }
-                    Ordering::Equal => {
-                        let mut old_vale = value;
-                        std::mem::swap(&mut node.value, &mut old_vale);
-                        Err(old_vale)
-                    }
-                    Ordering::Greater => {
-                        let (right_root, new_node) =
-                            Self::add_rec(node.right.take(), Some(weak), key, value);
-                        node.right = Some(right_root);
-                        new_node
-                    }
-                };
-                drop(node);
-                (node_rc, new_node)
+    // index 必须是本树的节点，因此对树的借用也就是对节点的借用
+    fn node(&self, index: Index) -> &Node<K, V> {
+        match unsafe { &*self.store.slot(index) } {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => unreachable!(),
+        }
+    }
+    fn node_mut(&mut self, index: Index) -> &mut Node<K, V> {
+        match unsafe { &mut *self.store.slot(index) } {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => unreachable!(),
+        }
+    }
+    fn size(&self, index: Index) -> Index {
+        if index == NIL {
+            0
+        } else {
+            self.node(index).size
+        }
+    }
+    fn update_size(&mut self, index: Index) {
+        let node = self.node(index);
+        let size = self.size(node.left) + self.size(node.right) + 1;
+        self.node_mut(index).size = size;
+    }
+    fn color(&self, index: Index) -> Color {
+        if index == NIL {
+            Black
+        } else {
+            self.node(index).color
+        }
+    }
+    fn set_color(&mut self, index: Index, color: Color) {
+        if index != NIL {
+            self.node_mut(index).color = color;
+        }
+    }
+    fn set_parent(&mut self, index: Index, parent: Index) {
+        if index != NIL {
+            self.node_mut(index).parent = parent;
+        }
+    }
+
+    // 优先复用本树释放的槽位，其次是其他树交还给存储的槽位
+    fn alloc(&mut self, node: Node<K, V>) -> Index {
+        if self.free_head == NIL {
+            (self.free_head, self.free_tail) = self.store.take_recycled();
+        }
+        let index = if self.free_head == NIL {
+            self.store.fresh()
+        } else {
+            let index = self.free_head;
+            self.free_head = match unsafe { &*self.store.slot(index) } {
+                Slot::Free(next_free) => *next_free,
+                Slot::Occupied(_) => unreachable!(),
+            };
+            if self.free_head == NIL {
+                self.free_tail = NIL;
             }
+            index
+        };
+        unsafe { *self.store.slot(index) = Slot::Occupied(node) };
+        index
+    }
+    fn dealloc(&mut self, index: Index) -> Node<K, V> {
+        let slot = std::mem::replace(
+            unsafe { &mut *self.store.slot(index) },
+            Slot::Free(self.free_head),
+        );
+        if self.free_head == NIL {
+            self.free_tail = index;
+        }
+        self.free_head = index;
+        match slot {
+            Slot::Occupied(node) => node,
+            Slot::Free(_) => unreachable!(),
         }
     }
-    fn is_left(child: &Node<K, V>, parent: &Node<K, V>) -> bool {
-        if let Some(left) = &parent.left {
-            std::ptr::eq(left.as_ptr(), child)
+    // 接管 other 释放的槽位，两棵树必须共用存储
+    fn adopt_free(&mut self, other: &mut Self) {
+        if other.free_head == NIL {
+            return;
+        }
+        if self.free_head == NIL {
+            self.free_head = other.free_head;
         } else {
-            false
-        }
-    }
-    fn replace_child(
-        parent: &Option<WeakNodeRef<K, V>>,
-        child: &Node<K, V>,
-        new_child: RcNodeRef<K, V>,
-    ) {
-        new_child.borrow_mut().parent = match parent {
-            Some(parent_weak) => {
-                let parent_rc = parent_weak.upgrade().unwrap();
-                let mut parent = parent_rc.borrow_mut();
-                if Self::is_left(child, &parent) {
-                    parent.left = Some(new_child.clone());
-                } else {
-                    parent.right = Some(new_child.clone());
-                }
-                Some(parent_weak.clone())
+            unsafe { *self.store.slot(self.free_tail) = Slot::Free(other.free_head) };
+        }
+        self.free_tail = other.free_tail;
+        other.free_head = NIL;
+        other.free_tail = NIL;
+    }
+
+    fn replace_child(&mut self, parent: Index, child: Index, new_child: Index) {
+        if parent == NIL {
+            self.root = new_child;
+        } else {
+            let parent = self.node_mut(parent);
+            if parent.left == child {
+                parent.left = new_child;
+            } else {
+                parent.right = new_child;
             }
-            None => None,
         }
     }
-    fn rotate(&mut self, node_rc: RcNodeRef<K, V>, is_right: bool) {
-        let mut node = node_rc.borrow_mut();
-        let node_child = if is_right {
-            node.left.clone()
+    fn rotate(&mut self, index: Index, is_right: bool) {
+        let node = self.node(index);
+        let (child, parent) = (if is_right { node.left } else { node.right }, node.parent);
+        let grand_child = if is_right {
+            let grand_child = self.node(child).right;
+            self.node_mut(index).left = grand_child;
+            self.node_mut(child).right = index;
+            grand_child
         } else {
-            node.right.clone()
+            let grand_child = self.node(child).left;
+            self.node_mut(index).right = grand_child;
+            self.node_mut(child).left = index;
+            grand_child
         };
-        match node_child {
-            None => {}
-            Some(node_child_rc) => {
-                Self::replace_child(&node.parent, &node, node_child_rc.clone());
-                let mut node_child = node_child_rc.borrow_mut();
-                if is_right {
-                    node.left = node_child.right.clone();
-                    if let Some(node_child_right) = &node_child.right {
-                        node_child_right.borrow_mut().parent = Some(Rc::downgrade(&node_rc))
-                    }
-                    node_child.right = Some(node_rc.clone());
-                } else {
-                    node.right = node_child.left.clone();
-                    if let Some(node_child_left) = &node_child.left {
-                        node_child_left.borrow_mut().parent = Some(Rc::downgrade(&node_rc))
-                    }
-                    node_child.left = Some(node_rc.clone());
-                }
-                node.parent = Some(Rc::downgrade(&node_child_rc));
+        self.set_parent(grand_child, index);
+        self.replace_child(parent, index, child);
+        self.node_mut(child).parent = parent;
+        self.node_mut(index).parent = child;
+        self.update_size(index);
+        self.update_size(child);
+    }
 
-                if self.root.clone().unwrap().as_ptr() == (node.deref_mut()) as *mut _ {
-                    drop(node_child);
-                    self.root = Some(node_child_rc)
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let mut parent = NIL;
+        let mut cur = self.root;
+        let mut is_left = false;
+        while cur != NIL {
+            let node = self.node(cur);
+            parent = cur;
+            match key.cmp(&node.key) {
+                Ordering::Less => {
+                    is_left = true;
+                    cur = node.left;
+                }
+                Ordering::Equal => {
+                    return Entry::Occupied(OccupiedEntry {
+                        tree: self,
+                        index: cur,
+                    })
+                }
+                Ordering::Greater => {
+                    is_left = false;
+                    cur = node.right;
                 }
             }
         }
+        Entry::Vacant(VacantEntry {
+            tree: self,
+            key,
+            parent,
+            is_left,
+        })
     }
-    fn fix_on_add(&mut self, mut new_node_rc: RcNodeRef<K, V>) {
+
+    pub fn add(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+    fn insert_at(&mut self, parent: Index, is_left: bool, key: K, value: V) -> Index {
+        let new_node = self.alloc(Node {
+            key,
+            value,
+            color: Red,
+            parent,
+            left: NIL,
+            right: NIL,
+            size: 1,
+        });
+        if parent == NIL {
+            self.root = new_node;
+        } else if is_left {
+            self.node_mut(parent).left = new_node;
+        } else {
+            self.node_mut(parent).right = new_node;
+        }
+        let mut ancestor = parent;
+        while ancestor != NIL {
+            self.node_mut(ancestor).size += 1;
+            ancestor = self.node(ancestor).parent;
+        }
+        self.fix_on_add(new_node);
+        new_node
+    }
+    // 返回根节点是否由红变黑，即整棵树的黑高是否增加
+    fn fix_on_add(&mut self, mut index: Index) -> bool {
         loop {
-            let new_node = new_node_rc.deref().borrow();
-            let parent_rc = match &new_node.parent {
-                None => {
-                    break;
-                }
-                Some(parent_weak) => parent_weak.upgrade().unwrap(),
-            };
-            let mut parent = parent_rc.borrow_mut();
-            if parent.color == Black {
+            let parent = self.node(index).parent;
+            if self.color(parent) == Black {
                 break;
             }
-            let grand_parent_rc = match &parent.parent {
-                None => {
-                    break;
-                }
-                Some(grand_parent) => grand_parent.upgrade().unwrap(),
-            };
-            let mut grand_parent = grand_parent_rc.borrow_mut();
-            let parent_is_left = Self::is_left(&parent, &grand_parent);
+            // 父节点为红色，因此一定不是根节点
+            let grand_parent = self.node(parent).parent;
+            let parent_is_left = self.node(grand_parent).left == parent;
             let uncle = if parent_is_left {
-                &grand_parent.right
+                self.node(grand_parent).right
             } else {
-                &grand_parent.left
+                self.node(grand_parent).left
             };
-            if let Some(uncle) = uncle {
-                let mut uncle = uncle.borrow_mut();
-                if uncle.color == Red {
-                    uncle.color = Black;
-                    parent.color = Black;
-                    drop(uncle);
-                    grand_parent.color = Red;
-                    drop(new_node);
-                    new_node_rc = grand_parent_rc.clone();
-                    continue;
-                }
+            if self.color(uncle) == Red {
+                self.set_color(uncle, Black);
+                self.set_color(parent, Black);
+                self.set_color(grand_parent, Red);
+                index = grand_parent;
+                continue;
             }
 
-            let self_is_left = Self::is_left(&new_node_rc.deref().borrow(), &parent);
+            let self_is_left = self.node(parent).left == index;
             if parent_is_left == self_is_left {
-                grand_parent.color = Red;
-                parent.color = Black;
-                drop(grand_parent);
-                drop(parent);
-                self.rotate(grand_parent_rc, parent_is_left);
-                return;
+                self.set_color(grand_parent, Red);
+                self.set_color(parent, Black);
+                self.rotate(grand_parent, parent_is_left);
+                break;
             } else {
-                drop(parent);
-                drop(new_node);
-                drop(grand_parent);
-                self.rotate(parent_rc.clone(), self_is_left);
-                new_node_rc = parent_rc;
+                self.rotate(parent, self_is_left);
+                index = parent;
             }
         }
 
-        self.root.as_ref().unwrap().borrow_mut().color = Black;
+        let root = self.root;
+        let grew = self.color(root) == Red;
+        self.set_color(root, Black);
+        grew
     }
-    pub fn add(&mut self, key: K, value: V) -> Option<V> {
-        let (root, new_node) = Self::add_rec(self.root.take(), None, key, value);
-        self.root = Some(root);
-        match new_node {
-            Ok(new_node) => {
-                self.length += 1;
-                self.fix_on_add(new_node);
-                None
-            }
-            Err(old_value) => Some(old_value),
+
+    fn left_most(&self, mut index: Index) -> Index {
+        while self.node(index).left != NIL {
+            index = self.node(index).left;
         }
+        index
     }
-
-    fn left_most(mut node_rc: RcNodeRef<K, V>) -> RcNodeRef<K, V> {
-        loop {
-            let node = node_rc.deref().borrow();
-            if let Some(left) = node.deref().left.clone() {
-                drop(node);
-                node_rc = left;
-            } else {
-                break;
-            }
+    fn right_most(&self, mut index: Index) -> Index {
+        while self.node(index).right != NIL {
+            index = self.node(index).right;
         }
-        node_rc
+        index
     }
-    fn right_most(mut node_rc: RcNodeRef<K, V>) -> RcNodeRef<K, V> {
-        loop {
-            let node = node_rc.deref().borrow();
-            if let Some(right) = node.deref().right.clone() {
-                drop(node);
-                node_rc = right;
+    fn first(&self) -> Index {
+        if self.root == NIL {
+            NIL
+        } else {
+            self.left_most(self.root)
+        }
+    }
+    fn last(&self) -> Index {
+        if self.root == NIL {
+            NIL
+        } else {
+            self.right_most(self.root)
+        }
+    }
+    fn next(&self, index: Index) -> Index {
+        let right = self.node(index).right;
+        if right != NIL {
+            return self.left_most(right);
+        }
+        let mut index = index;
+        let mut parent = self.node(index).parent;
+        while parent != NIL && self.node(parent).right == index {
+            index = parent;
+            parent = self.node(parent).parent;
+        }
+        parent
+    }
+    fn prev(&self, index: Index) -> Index {
+        let left = self.node(index).left;
+        if left != NIL {
+            return self.right_most(left);
+        }
+        let mut index = index;
+        let mut parent = self.node(index).parent;
+        while parent != NIL && self.node(parent).left == index {
+            index = parent;
+            parent = self.node(parent).parent;
+        }
+        parent
+    }
+    // 第一个不在下界之下的节点
+    fn lower_bound(&self, bound: Bound<&K>) -> Index {
+        let mut found = NIL;
+        let mut cur = self.root;
+        while cur != NIL {
+            let node = self.node(cur);
+            let in_bound = match bound {
+                Bound::Included(start) => node.key >= *start,
+                Bound::Excluded(start) => node.key > *start,
+                Bound::Unbounded => true,
+            };
+            cur = if in_bound {
+                found = cur;
+                node.left
             } else {
-                break;
-            }
+                node.right
+            };
         }
-        node_rc
+        found
     }
-    fn first_right_parent(mut node_rc: RcNodeRef<K, V>) -> Option<RcNodeRef<K, V>> {
-        loop {
-            let node = node_rc.deref().borrow();
-            if let Some(parent) = node.parent.clone() {
-                let parent = parent.upgrade().unwrap();
-                if Self::is_left(&node.borrow(), &parent.deref().borrow()) {
-                    return Some(parent);
-                }
-                drop(node);
-                node_rc = parent
+    // 最后一个不在上界之上的节点
+    fn upper_bound(&self, bound: Bound<&K>) -> Index {
+        let mut found = NIL;
+        let mut cur = self.root;
+        while cur != NIL {
+            let node = self.node(cur);
+            let in_bound = match bound {
+                Bound::Included(end) => node.key <= *end,
+                Bound::Excluded(end) => node.key < *end,
+                Bound::Unbounded => true,
+            };
+            cur = if in_bound {
+                found = cur;
+                node.right
             } else {
-                break;
-            }
+                node.left
+            };
         }
-        None
+        found
     }
-    fn next(node_rc: RcNodeRef<K, V>) -> Option<RcNodeRef<K, V>> {
-        let node = node_rc.deref().borrow();
-        let right = &node.right;
-        match right {
-            None => {
-                drop(node);
-                Self::first_right_parent(node_rc)
-            }
-            Some(right) => Some(Self::left_most(right.clone())),
+    fn find(&self, key: &K) -> Index {
+        let mut cur = self.root;
+        while cur != NIL {
+            let node = self.node(cur);
+            cur = match key.cmp(&node.key) {
+                Ordering::Less => node.left,
+                Ordering::Equal => return cur,
+                Ordering::Greater => node.right,
+            };
         }
+        NIL
+    }
+    fn pair_ref(&self, index: Index) -> Option<PairRef<'_, K, V>> {
+        if index == NIL {
+            return None;
+        }
+        let node = self.node(index);
+        Some(PairRef {
+            key: &node.key,
+            value: &node.value,
+        })
+    }
+
+    pub fn get(&self, key: &K) -> Option<PairRef<'_, K, V>> {
+        self.pair_ref(self.find(key))
     }
-    pub fn iter(&self) -> Iter<K, V> {
+    pub fn get_mut(&mut self, key: &K) -> Option<PairRefMut<'_, K, V>> {
+        let index = self.find(key);
+        if index == NIL {
+            return None;
+        }
+        let Node { key, value, .. } = self.node_mut(index);
+        Some(PairRefMut { key, value })
+    }
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key) != NIL
+    }
+    pub fn first_key_value(&self) -> Option<PairRef<'_, K, V>> {
+        self.pair_ref(self.first())
+    }
+    pub fn last_key_value(&self) -> Option<PairRef<'_, K, V>> {
+        self.pair_ref(self.last())
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
-            cur: self.root.clone().map(Self::left_most),
-            _phantom: Default::default(),
+            tree: self,
+            cur: self.first(),
+        }
+    }
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let front = self.lower_bound(range.start_bound());
+        let back = self.upper_bound(range.end_bound());
+        if front == NIL || back == NIL || self.node(front).key > self.node(back).key {
+            Range {
+                tree: self,
+                front: NIL,
+                back: NIL,
+            }
+        } else {
+            Range {
+                tree: self,
+                front,
+                back,
+            }
         }
     }
-    fn swap_key_and_value(a: &mut Node<K, V>, b: &mut Node<K, V>) {
-        std::mem::swap(&mut a.key, &mut b.key);
-        std::mem::swap(&mut a.value, &mut b.value);
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { iter: self.iter() }
     }
-    fn fix_on_remove(&mut self, mut node_rc: RcNodeRef<K, V>) {
-        loop {
-            let node = node_rc.deref().borrow();
-            if node.color == Red {
-                break;
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { iter: self.iter() }
+    }
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            order: self.in_order().into_iter(),
+            store: &self.store,
+        }
+    }
+
+    fn in_order(&self) -> Vec<Index> {
+        let mut order = Vec::with_capacity(self.length());
+        let mut cur = self.first();
+        while cur != NIL {
+            order.push(cur);
+            cur = self.next(cur);
+        }
+        order
+    }
+    fn transplant(&mut self, index: Index, new_index: Index) {
+        let parent = self.node(index).parent;
+        self.replace_child(parent, index, new_index);
+        self.set_parent(new_index, parent);
+    }
+    fn fix_on_remove(&mut self, mut index: Index, mut parent: Index) {
+        while index != self.root && self.color(index) == Black {
+            let is_left = self.node(parent).left == index;
+            let mut brother = if is_left {
+                self.node(parent).right
+            } else {
+                self.node(parent).left
+            };
+            if self.color(brother) == Red {
+                self.set_color(brother, Black);
+                self.set_color(parent, Red);
+                self.rotate(parent, !is_left);
+                brother = if is_left {
+                    self.node(parent).right
+                } else {
+                    self.node(parent).left
+                };
             }
-            let parent_rc = match &node.parent {
-                None => {
-                    break;
-                }
-                Some(parent) => parent.upgrade().unwrap(),
+
+            let (brother_close, brother_far) = if is_left {
+                (self.node(brother).left, self.node(brother).right)
+            } else {
+                (self.node(brother).right, self.node(brother).left)
             };
-            let mut parent = parent_rc.deref().borrow_mut();
-            let is_left = Self::is_left(&node, &parent);
-            let brother_rc = if is_left {
-                parent.right.clone().unwrap()
+            if self.color(brother_close) == Black && self.color(brother_far) == Black {
+                self.set_color(brother, Red);
+                index = parent;
+                parent = self.node(parent).parent;
+                continue;
+            }
+
+            if self.color(brother_far) == Black {
+                self.set_color(brother_close, Black);
+                self.set_color(brother, Red);
+                self.rotate(brother, is_left);
+                brother = brother_close;
+            }
+            let brother_far = if is_left {
+                self.node(brother).right
             } else {
-                parent.left.clone().unwrap()
+                self.node(brother).left
             };
-            let mut brother = brother_rc.deref().borrow_mut();
-            if brother.color == Red {
-                brother.color = Black;
-                parent.color = Red;
-                drop(parent);
-                drop(brother);
-                self.rotate(parent_rc, !is_left);
+            self.set_color(brother, self.color(parent));
+            self.set_color(parent, Black);
+            self.set_color(brother_far, Black);
+            self.rotate(parent, !is_left);
+            index = self.root;
+        }
+        self.set_color(index, Black);
+    }
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.find(key);
+        if index == NIL {
+            return None;
+        }
+        Some(self.remove_at(index).1)
+    }
+    fn remove_at(&mut self, index: Index) -> (K, V) {
+        let node = self.node(index);
+        let (left, right) = (node.left, node.right);
+        // 实际被移出树的位置到根节点路径上的子树都少了一个节点
+        let removed = if left == NIL || right == NIL {
+            index
+        } else {
+            self.left_most(right)
+        };
+        let mut ancestor = self.node(removed).parent;
+        while ancestor != NIL {
+            self.node_mut(ancestor).size -= 1;
+            ancestor = self.node(ancestor).parent;
+        }
+        let node = self.node(index);
+        // 实际被移出树的位置的颜色，及顶替该位置的节点和其父节点
+        let (removed_color, child, child_parent) = if left == NIL {
+            let parent = node.parent;
+            let color = node.color;
+            self.transplant(index, right);
+            (color, right, parent)
+        } else if right == NIL {
+            let parent = node.parent;
+            let color = node.color;
+            self.transplant(index, left);
+            (color, left, parent)
+        } else {
+            let successor = self.left_most(right);
+            let successor_node = self.node(successor);
+            let (color, child) = (successor_node.color, successor_node.right);
+            let child_parent = if successor_node.parent == index {
+                successor
             } else {
-                if let Some(brother_far) = if is_left {
-                    brother.right.clone()
-                } else {
-                    brother.left.clone()
-                } {
-                    let mut brother_far = brother_far.borrow_mut();
-                    if brother_far.color == Red {
-                        brother.color = parent.color;
-                        parent.color = Black;
-                        brother_far.color = Black;
-                        drop(parent);
-                        drop(brother);
-                        self.rotate(parent_rc, !is_left);
-                        break;
-                    }
-                }
+                let child_parent = successor_node.parent;
+                self.transplant(successor, child);
+                self.node_mut(successor).right = right;
+                self.node_mut(right).parent = successor;
+                child_parent
+            };
+            self.transplant(index, successor);
+            let (node_color, node_size) = (self.node(index).color, self.node(index).size);
+            let successor_node = self.node_mut(successor);
+            successor_node.left = left;
+            successor_node.color = node_color;
+            successor_node.size = node_size;
+            self.node_mut(left).parent = successor;
+            (color, child, child_parent)
+        };
+        if removed_color == Black {
+            self.fix_on_remove(child, child_parent);
+        }
 
-                if let Some(brother_close) = if is_left {
-                    brother.left.clone()
-                } else {
-                    brother.right.clone()
-                } {
-                    let mut brother_close = brother_close.borrow_mut();
-                    if brother_close.color == Red {
-                        brother.color = Red;
-                        brother_close.color = Black;
-                        drop(brother);
-                        drop(brother_close);
-                        drop(parent);
-                        self.rotate(brother_rc, is_left);
-                        continue;
-                    }
-                }
+        let node = self.dealloc(index);
+        (node.key, node.value)
+    }
 
-                brother.color = Red;
-                if parent.color == Red {
-                    parent.color = Black;
-                    break;
-                }
+    // 以 key 为中间节点连接两棵树，left 的键必须都小于 key，right 的键必须都大于 key
+    // 两棵树共用节点存储时（例如由 split_off 或 new_in 得到）只沿黑高重新链接节点，为 O(log n)；
+    // 否则先把较小的树逐个节点搬进较大的树的存储
+    pub fn join(left: Self, key: K, value: V, right: Self) -> Self {
+        assert!(left.last_key_value().is_none_or(|pair| *pair.key() < key));
+        assert!(right.first_key_value().is_none_or(|pair| *pair.key() > key));
+        let (mut tree, left, right) = Self::merge_stores(left, right);
+        let middle = tree.alloc(Node {
+            key,
+            value,
+            color: Red,
+            parent: NIL,
+            left: NIL,
+            right: NIL,
+            size: 1,
+        });
+        tree.root = tree.join_parts(left, middle, right).root;
+        tree
+    }
+
+    // 把不小于 key 的键移到返回的树中，返回的树与本树共用节点存储，为 O(log n)
+    pub fn split_off(&mut self, key: &K) -> Self {
+        let root = self.root_part();
+        let (left, found, right) = self.split_part(root, key);
+        let right = if found == NIL {
+            right
+        } else {
+            self.join_parts(EMPTY, found, right)
+        };
+        self.root = left.root;
+        let mut other = Self::new_in(self);
+        other.root = right.root;
+        other
+    }
+
+    // 把 other 的键值对全部移入本树，键相同时保留 other 的值
+    // 两棵树的键不相交时按 join 连接，共用节点存储时为 O(log n)，否则退化为 union
+    pub fn append(&mut self, other: &mut Self) {
+        // 用共用存储的空树占位，避免分配新的存储
+        let placeholder = Self::new_in(self);
+        let left = std::mem::replace(self, placeholder);
+        let placeholder = Self::new_in(other);
+        let right = std::mem::replace(other, placeholder);
+        *self = match (left.last_key_value(), right.first_key_value()) {
+            (None, _) | (_, None) => Self::concat(left, right),
+            (Some(last), Some(first)) if last.key() < first.key() => Self::concat(left, right),
+            _ => match (left.first_key_value(), right.last_key_value()) {
+                (Some(first), Some(last)) if last.key() < first.key() => Self::concat(right, left),
+                _ => left.union(right),
+            },
+        };
+    }
+
+    // 两棵树的并集，键相同时保留 other 的值，为 O(m log(n / m + 1))，m 为较小的树的大小
+    pub fn union(self, other: Self) -> Self {
+        let (mut tree, left, right) = Self::merge_stores(self, other);
+        tree.root = tree.union_parts(left, right).root;
+        tree
+    }
 
-                drop(node);
-                drop(parent);
-                node_rc = parent_rc;
+    // 两棵树的交集，保留 self 的值
+    pub fn intersection(self, other: Self) -> Self {
+        let (mut tree, left, right) = Self::merge_stores(self, other);
+        tree.root = tree.intersection_parts(left, right).root;
+        tree
+    }
+
+    // self 中不在 other 里的键
+    pub fn difference(self, other: Self) -> Self {
+        let (mut tree, left, right) = Self::merge_stores(self, other);
+        tree.root = tree.difference_parts(left, right).root;
+        tree
+    }
+
+    fn black_height(&self, mut index: Index) -> usize {
+        let mut black_height = 0;
+        while index != NIL {
+            if self.color(index) == Black {
+                black_height += 1;
             }
+            index = self.node(index).left;
         }
+        black_height
     }
-    fn remove_node(&mut self, node_rc: RcNodeRef<K, V>) -> RcNodeRef<K, V> {
-        let mut node = node_rc.deref().borrow_mut();
-        if let Some(left) = &node.left {
-            let left = Self::right_most(left.clone());
-            Self::swap_key_and_value(&mut node, &mut left.borrow_mut());
-            drop(node);
-            self.remove_node(left)
-        } else if let Some(right) = &node.right {
-            let right = Self::left_most(right.clone());
-            Self::swap_key_and_value(&mut node, &mut right.borrow_mut());
-            drop(node);
-            self.remove_node(right)
+    fn root_part(&self) -> Part {
+        Part {
+            root: self.root,
+            black_height: self.black_height(self.root),
+        }
+    }
+    // 把子节点从父节点上摘下，黑高为摘下前子树的黑高
+    fn detach(&mut self, index: Index, black_height: usize) -> Part {
+        if index == NIL {
+            return EMPTY;
+        }
+        let node = self.node_mut(index);
+        node.parent = NIL;
+        if node.color == Red {
+            node.color = Black;
+            Part {
+                root: index,
+                black_height: black_height + 1,
+            }
         } else {
-            drop(node);
-            self.fix_on_remove(node_rc.clone());
-            let mut node = node_rc.borrow_mut();
-            if let Some(parent) = &node.parent {
-                let parent = parent.upgrade().unwrap();
-                let mut parent = parent.deref().borrow_mut();
-                if Self::is_left(&node, &parent) {
-                    parent.left = None;
-                } else {
-                    parent.right = None;
-                }
-                node.parent = None;
+            Part {
+                root: index,
+                black_height,
             }
-            drop(node);
-            node_rc
         }
     }
-    pub fn remove(&mut self, key: &K) -> Option<V> {
-        let mut node_opt = self.root.clone();
-        loop {
-            let node_rc = match &node_opt {
-                None => {
-                    return None;
-                }
-                Some(node) => node.clone(),
+    fn detach_children(&mut self, part: Part) -> (Part, Part) {
+        let node = self.node(part.root);
+        let (left, right) = (node.left, node.right);
+        (
+            self.detach(left, part.black_height - 1),
+            self.detach(right, part.black_height - 1),
+        )
+    }
+    // middle 是已脱离树的节点，left 中的键都小于它，right 中的键都大于它
+    fn join_parts(&mut self, left: Part, middle: Index, right: Part) -> Part {
+        if left.black_height == right.black_height {
+            let size = self.size(left.root) + self.size(right.root) + 1;
+            let node = self.node_mut(middle);
+            node.size = size;
+            node.color = Black;
+            node.parent = NIL;
+            node.left = left.root;
+            node.right = right.root;
+            self.set_parent(left.root, middle);
+            self.set_parent(right.root, middle);
+            return Part {
+                root: middle,
+                black_height: left.black_height + 1,
             };
+        }
 
-            let node = node_rc.deref().borrow();
-            let next = match key.cmp(&node.key) {
-                Ordering::Less => node.left.clone(),
-                Ordering::Equal => {
-                    drop(node);
-                    let removed_node = self.remove_node(node_rc);
-                    if removed_node.as_ptr() == self.root.as_ref().unwrap().as_ptr() {
-                        self.root = None
-                    }
-                    drop(node_opt);
-                    self.length -= 1;
-                    let removed_node = Rc::try_unwrap(removed_node).ok().unwrap();
-                    return Some(removed_node.into_inner().value);
-                }
-                Ordering::Greater => node.right.clone(),
+        let left_is_taller = left.black_height > right.black_height;
+        let (tall, short) = if left_is_taller {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        // 沿较高的树靠近较矮的树的一侧下降，找到黑高相同的黑色节点，
+        // 经过的节点的子树都将加入较矮的树和中间节点
+        let added = self.size(short.root) + 1;
+        let mut parent = NIL;
+        let mut cur = tall.root;
+        let mut black_height = tall.black_height;
+        while self.color(cur) == Red || black_height != short.black_height {
+            if self.color(cur) == Black {
+                black_height -= 1;
+            }
+            self.node_mut(cur).size += added;
+            parent = cur;
+            cur = if left_is_taller {
+                self.node(cur).right
+            } else {
+                self.node(cur).left
             };
+        }
+        let (middle_left, middle_right) = if left_is_taller {
+            (cur, short.root)
+        } else {
+            (short.root, cur)
+        };
+        let size = self.size(cur) + added;
+        let node = self.node_mut(middle);
+        node.size = size;
+        node.color = Red;
+        node.parent = parent;
+        node.left = middle_left;
+        node.right = middle_right;
+        self.set_parent(middle_left, middle);
+        self.set_parent(middle_right, middle);
+        if left_is_taller {
+            self.node_mut(parent).right = middle;
+        } else {
+            self.node_mut(parent).left = middle;
+        }
 
-            drop(node);
-            node_opt = next;
+        self.root = tall.root;
+        let grew = self.fix_on_add(middle);
+        Part {
+            root: self.root,
+            black_height: tall.black_height + grew as usize,
+        }
+    }
+    // 没有中间节点时，取出 right 的最小节点作为中间节点
+    fn concat_parts(&mut self, left: Part, right: Part) -> Part {
+        if right.root == NIL {
+            return left;
+        }
+        self.root = right.root;
+        let min = self.left_most(right.root);
+        let (key, value) = self.remove_at(min);
+        let right = self.root_part();
+        let middle = self.alloc(Node {
+            key,
+            value,
+            color: Red,
+            parent: NIL,
+            left: NIL,
+            right: NIL,
+            size: 1,
+        });
+        self.join_parts(left, middle, right)
+    }
+    // 返回小于 key 的部分、等于 key 的节点和大于 key 的部分
+    fn split_part(&mut self, part: Part, key: &K) -> (Part, Index, Part) {
+        if part.root == NIL {
+            return (EMPTY, NIL, EMPTY);
+        }
+        let (left, right) = self.detach_children(part);
+        match key.cmp(&self.node(part.root).key) {
+            Ordering::Less => {
+                let (left_left, found, left_right) = self.split_part(left, key);
+                (
+                    left_left,
+                    found,
+                    self.join_parts(left_right, part.root, right),
+                )
+            }
+            Ordering::Equal => (left, part.root, right),
+            Ordering::Greater => {
+                let (right_left, found, right_right) = self.split_part(right, key);
+                (
+                    self.join_parts(left, part.root, right_left),
+                    found,
+                    right_right,
+                )
+            }
         }
     }
+    fn union_parts(&mut self, left: Part, right: Part) -> Part {
+        if left.root == NIL {
+            return right;
+        }
+        if right.root == NIL {
+            return left;
+        }
+        // 先把根节点取出，才能在拆分 right 时借用它的键
+        let (left_left, left_right) = self.detach_children(left);
+        let pivot = self.dealloc(left.root);
+        let (right_left, found, right_right) = self.split_part(right, &pivot.key);
+        let middle = if found == NIL {
+            self.alloc(pivot)
+        } else {
+            found
+        };
+        let lower = self.union_parts(left_left, right_left);
+        let upper = self.union_parts(left_right, right_right);
+        self.join_parts(lower, middle, upper)
+    }
+    fn intersection_parts(&mut self, left: Part, right: Part) -> Part {
+        if left.root == NIL || right.root == NIL {
+            self.discard_subtree(left.root);
+            self.discard_subtree(right.root);
+            return EMPTY;
+        }
+        let (left_left, left_right) = self.detach_children(left);
+        let pivot = self.dealloc(left.root);
+        let (right_left, found, right_right) = self.split_part(right, &pivot.key);
+        let lower = self.intersection_parts(left_left, right_left);
+        let upper = self.intersection_parts(left_right, right_right);
+        if found == NIL {
+            self.concat_parts(lower, upper)
+        } else {
+            self.dealloc(found);
+            let middle = self.alloc(pivot);
+            self.join_parts(lower, middle, upper)
+        }
+    }
+    fn difference_parts(&mut self, left: Part, right: Part) -> Part {
+        if left.root == NIL || right.root == NIL {
+            self.discard_subtree(right.root);
+            return left;
+        }
+        let (right_left, right_right) = self.detach_children(right);
+        let pivot = self.dealloc(right.root);
+        let (left_left, found, left_right) = self.split_part(left, &pivot.key);
+        if found != NIL {
+            self.dealloc(found);
+        }
+        let lower = self.difference_parts(left_left, right_left);
+        let upper = self.difference_parts(left_right, right_right);
+        self.concat_parts(lower, upper)
+    }
+    fn discard_subtree(&mut self, root: Index) {
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            if index != NIL {
+                let node = self.dealloc(index);
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+    }
+
+    fn concat(left: Self, right: Self) -> Self {
+        let (mut tree, left, right) = Self::merge_stores(left, right);
+        tree.root = tree.concat_parts(left, right).root;
+        tree
+    }
+    // 把两棵树放进同一个节点存储，返回根为空的合并后的树及两棵树各自的位置
+    // 不共用存储时，把较小的树逐个节点搬进较大的树的存储
+    fn merge_stores(left: Self, right: Self) -> (Self, Part, Part) {
+        let (mut left, mut right) = if Arc::ptr_eq(&left.store, &right.store) {
+            (left, right)
+        } else if left.length() >= right.length() {
+            let right = right.move_into(&left);
+            (left, right)
+        } else {
+            let left = left.move_into(&right);
+            (left, right)
+        };
+        let (left_part, right_part) = (left.root_part(), right.root_part());
+        left.root = NIL;
+        right.root = NIL;
+        left.adopt_free(&mut right);
+        (left, left_part, right_part)
+    }
+    // 把本树的节点搬到一棵与 other 共用存储的新树中
+    fn move_into(mut self, other: &Self) -> Self {
+        let mut moved = Self::new_in(other);
+        let root = std::mem::replace(&mut self.root, NIL);
+        moved.root = Self::move_subtree(&mut self, root, &mut moved);
+        moved
+    }
+    // 把 from 中以 index 为根的子树搬到 to 中，返回新的根
+    fn move_subtree(from: &mut Self, index: Index, to: &mut Self) -> Index {
+        if index == NIL {
+            return NIL;
+        }
+        let mut node = from.dealloc(index);
+        node.left = Self::move_subtree(from, node.left, to);
+        node.right = Self::move_subtree(from, node.right, to);
+        node.parent = NIL;
+        let (left, right) = (node.left, node.right);
+        let new_index = to.alloc(node);
+        to.set_parent(left, new_index);
+        to.set_parent(right, new_index);
+        new_index
+    }
+}
+
+/// 对树中一个键值对的只读借用
+pub struct PairRef<'t, K: Ord, V> {
+    key: &'t K,
+    value: &'t V,
+}
+
+impl<'t, K: Ord, V> PairRef<'t, K, V> {
+    pub fn key(&self) -> &'t K {
+        self.key
+    }
+    pub fn value(&self) -> &'t V {
+        self.value
+    }
+}
+
+/// 对树中一个键值对的可变借用，只有值可以被修改
+pub struct PairRefMut<'t, K: Ord, V> {
+    key: &'t K,
+    value: &'t mut V,
+}
+
+impl<'t, K: Ord, V> PairRefMut<'t, K, V> {
+    pub fn key(&self) -> &'t K {
+        self.key
+    }
+    pub fn value(&self) -> &V {
+        self.value
+    }
+    pub fn value_mut(&mut self) -> &mut V {
+        self.value
+    }
 }
 
-pub struct Iter<'n, K: Ord, V> {
-    cur: Option<RcNodeRef<K, V>>,
-    _phantom: PhantomData<&'n ()>,
+pub struct Iter<'t, K: Ord, V> {
+    tree: &'t RedBlackTree<K, V>,
+    cur: Index,
 }
 
-impl<'n, K: Ord, V> Iterator for Iter<'n, K, V> {
-    type Item = RcNodeRef<K, V>;
+impl<'t, K: Ord, V> Iterator for Iter<'t, K, V> {
+    type Item = PairRef<'t, K, V>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.cur.take() {
-            None => None,
-            Some(cur) => {
-                self.cur = RedBlackTree::next(cur.clone());
-                Some(cur)
-            }
+        let pair = self.tree.pair_ref(self.cur)?;
+        self.cur = self.tree.next(self.cur);
+        Some(pair)
+    }
+}
+
+impl<'t, K: Ord, V> IntoIterator for &'t RedBlackTree<K, V> {
+    type Item = PairRef<'t, K, V>;
+    type IntoIter = Iter<'t, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Range<'t, K: Ord, V> {
+    tree: &'t RedBlackTree<K, V>,
+    // 两端尚未返回的节点，范围为空时均为 NIL
+    front: Index,
+    back: Index,
+}
+
+impl<'t, K: Ord, V> Iterator for Range<'t, K, V> {
+    type Item = PairRef<'t, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cur = self.front;
+        if cur == NIL {
+            return None;
+        }
+        if cur == self.back {
+            self.front = NIL;
+            self.back = NIL;
+        } else {
+            self.front = self.tree.next(cur);
+        }
+        self.tree.pair_ref(cur)
+    }
+}
+
+impl<'t, K: Ord, V> DoubleEndedIterator for Range<'t, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let cur = self.back;
+        if cur == NIL {
+            return None;
+        }
+        if cur == self.front {
+            self.front = NIL;
+            self.back = NIL;
+        } else {
+            self.back = self.tree.prev(cur);
+        }
+        self.tree.pair_ref(cur)
+    }
+}
+
+pub enum Entry<'t, K: Ord, V> {
+    Occupied(OccupiedEntry<'t, K, V>),
+    Vacant(VacantEntry<'t, K, V>),
+}
+
+pub struct OccupiedEntry<'t, K: Ord, V> {
+    tree: &'t mut RedBlackTree<K, V>,
+    index: Index,
+}
+
+pub struct VacantEntry<'t, K: Ord, V> {
+    tree: &'t mut RedBlackTree<K, V>,
+    key: K,
+    // 查找时停下的位置，插入时直接挂在这里
+    parent: Index,
+    is_left: bool,
+}
+
+impl<'t, K: Ord, V> Entry<'t, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+    pub fn or_insert(self, default: V) -> &'t mut V {
+        self.or_insert_with(|| default)
+    }
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'t mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'t, K: Ord, V: Default> Entry<'t, K, V> {
+    pub fn or_default(self) -> &'t mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'t, K: Ord, V> OccupiedEntry<'t, K, V> {
+    pub fn key(&self) -> &K {
+        &self.tree.node(self.index).key
+    }
+    pub fn get(&self) -> &V {
+        &self.tree.node(self.index).value
+    }
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.tree.node_mut(self.index).value
+    }
+    pub fn into_mut(self) -> &'t mut V {
+        &mut self.tree.node_mut(self.index).value
+    }
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+    pub fn remove_entry(self) -> (K, V) {
+        self.tree.remove_at(self.index)
+    }
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+impl<'t, K: Ord, V> VacantEntry<'t, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+    pub fn into_key(self) -> K {
+        self.key
+    }
+    pub fn insert(self, value: V) -> &'t mut V {
+        // 节点在存储中的位置不会因旋转而改变
+        let index = self
+            .tree
+            .insert_at(self.parent, self.is_left, self.key, value);
+        &mut self.tree.node_mut(index).value
+    }
+}
+
+pub struct Keys<'t, K: Ord, V> {
+    iter: Iter<'t, K, V>,
+}
+
+impl<'t, K: Ord + Clone, V> Iterator for Keys<'t, K, V> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|pair| pair.key().clone())
+    }
+}
+
+pub struct Values<'t, K: Ord, V> {
+    iter: Iter<'t, K, V>,
+}
+
+impl<'t, K: Ord, V: Clone> Iterator for Values<'t, K, V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|pair| pair.value().clone())
+    }
+}
+
+// 持有树的可变借用，order 中的每个节点只交出一次
+pub struct ValuesMut<'t, K: Ord, V> {
+    order: std::vec::IntoIter<Index>,
+    store: &'t Store<K, V>,
+}
+
+impl<'t, K: Ord, V> Iterator for ValuesMut<'t, K, V> {
+    type Item = PairRefMut<'t, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.order.next()?;
+        match unsafe { &mut *self.store.slot(index) } {
+            Slot::Occupied(Node { key, value, .. }) => Some(PairRefMut { key, value }),
+            Slot::Free(_) => unreachable!(),
+        }
+    }
+}
+
+// 树的根已置空，按 order 依次取出节点，未取出的节点在 drop 时释放
+pub struct IntoIter<K: Ord, V> {
+    order: std::vec::IntoIter<Index>,
+    tree: RedBlackTree<K, V>,
+}
+
+impl<K: Ord, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.order.next()?;
+        let node = self.tree.dealloc(index);
+        Some((node.key, node.value))
+    }
+}
+
+impl<K: Ord, V> Drop for IntoIter<K, V> {
+    fn drop(&mut self) {
+        for index in self.order.by_ref() {
+            self.tree.dealloc(index);
+        }
+    }
+}
+
+impl<K: Ord, V> IntoIterator for RedBlackTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        let order = self.in_order();
+        self.root = NIL;
+        IntoIter {
+            order: order.into_iter(),
+            tree: self,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::ops::Deref;
-
     use rand::prelude::SliceRandom;
     use rand::Rng;
 
@@ -514,24 +1416,6 @@ mod tests {
         }
     }
 
-    macro_rules! new_nodes {
-        ($num:expr) => {{
-            let mut nodes = vec![];
-            nodes.reserve($num);
-            for i in 0..$num {
-                nodes.push(Rc::new(RefCell::new(Node {
-                    key: i,
-                    value: (),
-                    color: Color::Red,
-                    parent: None,
-                    left: None,
-                    right: None,
-                })));
-            }
-            nodes
-        }};
-    }
-
     #[test]
     fn add() {
         let mut tree = RedBlackTree::default();
@@ -549,296 +1433,400 @@ mod tests {
         assert_eq!(tree.add(99, 99), Some(99));
         assert_eq!(tree.length(), 100);
         assert_eq!(
-            tree.iter()
-                .map(|node| node.deref().borrow().key)
-                .collect::<Vec<i32>>(),
+            tree.iter().map(|pair| *pair.key()).collect::<Vec<i32>>(),
             (0..NUM).collect::<Vec<i32>>()
         );
     }
 
     #[test]
-    fn rotate_left() {
+    fn iter() {
         let mut tree = RedBlackTree::default();
-        let nodes = new_nodes!(10);
-        tree.root = Some(nodes[0].clone());
-        tree.root.as_ref().unwrap().borrow_mut().right = Some(nodes[1].clone());
-        nodes[1].borrow_mut().parent = Some(Rc::downgrade(&nodes[0]));
-        tree.rotate(tree.root.clone().unwrap(), false);
-        //   1
-        // 0
-        assert_eq!(tree.root.clone().unwrap().borrow_mut().key, 1);
-        assert_eq!(
-            tree.root
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .key,
-            0
-        );
-        assert!(tree.root.clone().unwrap().borrow_mut().right.is_none());
-        assert_eq!(
-            tree.root
-                .clone()
-                .unwrap()
-                .deref()
-                .borrow()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .parent
-                .clone()
-                .unwrap()
-                .upgrade()
-                .unwrap()
-                .deref()
-                .borrow()
-                .key,
-            1
-        );
-        assert!(nodes[1].borrow_mut().parent.is_none());
-
-        tree.root.clone().unwrap().borrow_mut().right = Some(nodes[2].clone());
-        tree.rotate(tree.root.clone().unwrap(), false);
-        //    2
-        //  1
-        // 0
-        assert_eq!(tree.root.clone().unwrap().borrow_mut().key, 2);
-        assert_eq!(
-            tree.root
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .key,
-            1
-        );
-        assert!(tree.root.clone().unwrap().borrow_mut().right.is_none());
+        let mut elements: Vec<i32> = (0..100).collect();
+        elements.shuffle(&mut rand::thread_rng());
+        elements.iter().for_each(|&element| {
+            tree.add(element, element);
+        });
+
+        for mut pair in tree.values_mut() {
+            *pair.value_mut() *= 10;
+        }
+        assert!((&tree)
+            .into_iter()
+            .map(|pair| (*pair.key(), *pair.value()))
+            .eq((0..100).map(|i| (i, i * 10))));
+        assert!(tree.keys().eq(0..100));
+        assert!(tree.values().eq((0..100).map(|i| i * 10)));
+
+        // 迭代器产生的借用结束后即可修改树
+        let pairs: Vec<PairRef<i32, i32>> = tree.iter().take(10).collect();
+        assert_eq!(*pairs[9].key(), 9);
+        drop(pairs);
+        for i in 0..50 {
+            assert_eq!(tree.remove(&i), Some(i * 10));
+        }
+        assert!(tree.is_valid());
+        assert!(tree.into_iter().eq((50..100).map(|i| (i, i * 10))));
+    }
+
+    #[test]
+    fn get() {
+        let mut tree = RedBlackTree::default();
+        assert!(tree.get(&0).is_none());
+        assert!(tree.first_key_value().is_none());
+        assert!(tree.last_key_value().is_none());
+
+        let mut elements: Vec<i32> = (0..100).collect();
+        elements.shuffle(&mut rand::thread_rng());
+        elements.iter().for_each(|&element| {
+            tree.add(element, element * 10);
+        });
+        for i in 0..100 {
+            let pair = tree.get(&i).unwrap();
+            assert_eq!(*pair.key(), i);
+            assert_eq!(*pair.value(), i * 10);
+            assert!(tree.contains_key(&i));
+        }
+        assert!(tree.get(&100).is_none());
+        assert!(!tree.contains_key(&-1));
+        assert_eq!(*tree.first_key_value().unwrap().key(), 0);
+        assert_eq!(*tree.last_key_value().unwrap().value(), 990);
+
+        *tree.get_mut(&50).unwrap().value_mut() += 1;
+        assert_eq!(*tree.get(&50).unwrap().value(), 501);
+        assert!(tree.get_mut(&100).is_none());
+        assert_eq!(tree.remove(&50), Some(501));
+        assert!(tree.is_valid());
+    }
+
+    #[test]
+    fn range() {
+        let mut tree = RedBlackTree::default();
+        let mut std_tree = std::collections::BTreeMap::new();
+        let mut elements: Vec<i32> = (0..200).map(|i| i * 2).collect();
+        let mut rng = rand::thread_rng();
+        elements.shuffle(&mut rng);
+        elements.iter().for_each(|&element| {
+            tree.add(element, element);
+            std_tree.insert(element, element);
+        });
+
+        let pairs = |iter: &mut dyn Iterator<Item = PairRef<i32, i32>>| {
+            iter.map(|pair| (*pair.key(), *pair.value()))
+                .collect::<Vec<_>>()
+        };
+        for _ in 0..500 {
+            let start = rng.gen_range(-5..405);
+            let end = rng.gen_range(start..410);
+            let expected: Vec<(i32, i32)> =
+                std_tree.range(start..end).map(|(&k, &v)| (k, v)).collect();
+            assert_eq!(pairs(&mut tree.range(start..end)), expected);
+            let mut reversed = expected.clone();
+            reversed.reverse();
+            assert_eq!(pairs(&mut tree.range(start..end).rev()), reversed);
+
+            let expected: Vec<i32> = std_tree.range(start..=end).map(|(&k, _)| k).collect();
+            let mut range = tree.range(start..=end);
+            let mut keys = vec![];
+            let mut back_keys = vec![];
+            loop {
+                match range.next() {
+                    None => break,
+                    Some(pair) => keys.push(*pair.key()),
+                }
+                match range.next_back() {
+                    None => break,
+                    Some(pair) => back_keys.push(*pair.key()),
+                }
+            }
+            back_keys.reverse();
+            keys.append(&mut back_keys);
+            assert_eq!(keys, expected);
+        }
+        assert_eq!(tree.range(..).count(), 200);
+        assert_eq!(tree.range(10..10).count(), 0);
+        assert_eq!(tree.range(401..).count(), 0);
+    }
+
+    #[test]
+    fn entry() {
+        let mut tree = RedBlackTree::default();
+        for word in "a b a c b a d".split(' ') {
+            tree.entry(word)
+                .and_modify(|count| *count += 1)
+                .or_insert(1);
+        }
+        assert!(tree.is_valid());
         assert_eq!(
-            tree.root
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .key,
-            0
+            tree.iter()
+                .map(|pair| (*pair.key(), *pair.value()))
+                .collect::<Vec<_>>(),
+            vec![("a", 3), ("b", 2), ("c", 1), ("d", 1)]
         );
-        fn get_left_left_parent<K: Ord + Copy, V>(root: &Option<RcNodeRef<K, V>>) -> K {
-            root.clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .deref()
-                .borrow()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .parent
-                .clone()
-                .unwrap()
-                .upgrade()
-                .unwrap()
-                .deref()
-                .borrow()
-                .key
-        }
-        assert_eq!(get_left_left_parent(&tree.root), 1);
-        fn get_left_parent<K: Ord + Copy, V>(root: &Option<RcNodeRef<K, V>>) -> K {
-            root.clone()
-                .unwrap()
-                .deref()
-                .borrow()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .parent
-                .clone()
-                .unwrap()
-                .upgrade()
-                .unwrap()
-                .deref()
-                .borrow()
-                .key
-        }
-        assert_eq!(get_left_parent(&tree.root), 2);
-
-        tree.root
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .left
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .right = Some(nodes[3].clone());
-        tree.root
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .left
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .right
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .right = Some(nodes[4].clone());
-
-        tree.root
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .left
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .right
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .right
-            .clone()
-            .unwrap()
-            .borrow_mut()
-            .parent = Some(Rc::downgrade(&nodes[3]));
-        let root_left = tree.root.clone().unwrap().deref().borrow().left.clone();
-        tree.rotate(root_left.unwrap(), false);
-        let root_left = tree.root.clone().unwrap().deref().borrow().left.clone();
-        //    2
-        //   3
-        //  1 4
-        // 0
-        assert_eq!(tree.root.clone().unwrap().borrow_mut().key, 2);
-        assert_eq!(root_left.clone().unwrap().borrow_mut().key, 3);
+
+        *tree.entry("e").or_insert_with(|| 10) += 1;
+        assert_eq!(*tree.get(&"e").unwrap().value(), 11);
+        assert_eq!(tree.entry("e").key(), &"e");
+        match tree.entry("a") {
+            Entry::Occupied(entry) => assert_eq!(entry.remove_entry(), ("a", 3)),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert!(!tree.contains_key(&"a"));
+        assert_eq!(tree.length(), 4);
+        assert!(tree.is_valid());
+    }
+
+    fn random_tree(
+        rng: &mut impl Rng,
+        len: usize,
+        keys: std::ops::Range<i32>,
+    ) -> (RedBlackTree<i32, i32>, std::collections::BTreeMap<i32, i32>) {
+        let mut tree = RedBlackTree::default();
+        let mut std_tree = std::collections::BTreeMap::new();
+        for _ in 0..len {
+            let (key, value) = (rng.gen_range(keys.clone()), rng.gen());
+            tree.add(key, value);
+            std_tree.insert(key, value);
+        }
+        (tree, std_tree)
+    }
+
+    fn assert_same(tree: &RedBlackTree<i32, i32>, std_tree: &std::collections::BTreeMap<i32, i32>) {
+        assert!(tree.is_valid());
+        assert_eq!(tree.length(), std_tree.len());
+        assert!(tree
+            .iter()
+            .map(|pair| (*pair.key(), *pair.value()))
+            .eq(std_tree.iter().map(|(&k, &v)| (k, v))));
+    }
+
+    #[test]
+    fn split_off_and_append() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let len = rng.gen_range(0..300);
+            let (mut tree, mut std_tree) = random_tree(&mut rng, len, 0..500);
+            let key = rng.gen_range(-10..510);
+            let mut other = tree.split_off(&key);
+            let mut std_other = std_tree.split_off(&key);
+            assert_same(&tree, &std_tree);
+            assert_same(&other, &std_other);
+
+            tree.append(&mut other);
+            std_tree.append(&mut std_other);
+            assert_same(&tree, &std_tree);
+            assert_same(&other, &std_other);
+
+            // 键的范围有重叠时退化为合并
+            let (mut overlapped, mut std_overlapped) = random_tree(&mut rng, len, 0..500);
+            tree.append(&mut overlapped);
+            std_tree.append(&mut std_overlapped);
+            assert_same(&tree, &std_tree);
+            assert_same(&overlapped, &std_overlapped);
+        }
+    }
+
+    #[test]
+    fn join() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (left_len, len) = (rng.gen_range(0..300), rng.gen_range(0..3000));
+            let (left, mut std_tree) = random_tree(&mut rng, left_len, 0..1000);
+            let (right, std_right) = random_tree(&mut rng, len, 1001..5000);
+            std_tree.insert(1000, -1);
+            std_tree.extend(std_right);
+            let tree = RedBlackTree::join(left, 1000, -1, right);
+            assert_same(&tree, &std_tree);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn join_unordered() {
+        let mut left = RedBlackTree::default();
+        left.add(5, 5);
+        RedBlackTree::join(left, 3, 3, RedBlackTree::default());
+    }
+
+    #[test]
+    fn set_operations() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (len_a, len_b) = (rng.gen_range(0..400), rng.gen_range(0..400));
+            let (a, std_a) = random_tree(&mut rng, len_a, 0..600);
+            let (b, std_b) = random_tree(&mut rng, len_b, 0..600);
+
+            let mut std_union = std_a.clone();
+            std_union.extend(std_b.iter().map(|(&k, &v)| (k, v)));
+            let std_intersection = std_a
+                .iter()
+                .filter(|(k, _)| std_b.contains_key(k))
+                .map(|(&k, &v)| (k, v))
+                .collect();
+            let std_difference = std_a
+                .iter()
+                .filter(|(k, _)| !std_b.contains_key(k))
+                .map(|(&k, &v)| (k, v))
+                .collect();
+
+            let copy = |tree: &std::collections::BTreeMap<i32, i32>| {
+                let mut copied = RedBlackTree::default();
+                tree.iter().for_each(|(&k, &v)| {
+                    copied.add(k, v);
+                });
+                copied
+            };
+            assert_same(&copy(&std_a).union(copy(&std_b)), &std_union);
+            assert_same(&a.intersection(copy(&std_b)), &std_intersection);
+            assert_same(&copy(&std_a).difference(b), &std_difference);
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let mut tree = RedBlackTree::default();
+        for i in 1..=4 {
+            tree.add(i, i);
+        }
+        // 2 为根，1 和 3 为黑色，4 为 3 的红色右子节点
+        assert_eq!(tree.validate(), Ok(()));
+        let root = tree.root;
+        let (one, three) = (tree.node(root).left, tree.node(root).right);
+        let four = tree.node(three).right;
+
+        tree.node_mut(root).color = Red;
+        assert_eq!(tree.validate(), Err(InvariantViolation::RedRoot));
+        tree.node_mut(root).color = Black;
+
+        tree.node_mut(one).color = Red;
         assert_eq!(
-            root_left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .key,
-            1
+            tree.validate(),
+            Err(InvariantViolation::BlackHeight {
+                path: vec![],
+                left: 0,
+                right: 1
+            })
         );
+        tree.node_mut(one).color = Black;
+
+        tree.node_mut(three).color = Red;
         assert_eq!(
-            root_left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .right
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .key,
-            4
+            tree.validate(),
+            Err(InvariantViolation::RedRed { path: vec![1, 1] })
         );
+        tree.node_mut(three).color = Black;
+
+        tree.node_mut(one).key = 100;
         assert_eq!(
-            root_left
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .key,
-            0
+            tree.validate(),
+            Err(InvariantViolation::KeyOrder { path: vec![0] })
         );
+        tree.node_mut(one).key = 1;
 
+        tree.node_mut(four).parent = root;
         assert_eq!(
-            tree.root
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .deref()
-                .borrow()
-                .left
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .parent
-                .clone()
-                .unwrap()
-                .upgrade()
-                .unwrap()
-                .deref()
-                .borrow()
-                .key,
-            1
+            tree.validate(),
+            Err(InvariantViolation::ParentLink { path: vec![1, 1] })
         );
-        assert_eq!(get_left_left_parent(&tree.root), 3);
+        tree.node_mut(four).parent = three;
+        assert!(tree.validate().is_ok());
+
+        tree.node_mut(root).size += 1;
         assert_eq!(
-            tree.root
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .left
-                .clone()
-                .unwrap()
-                .deref()
-                .borrow()
-                .right
-                .clone()
-                .unwrap()
-                .borrow_mut()
-                .parent
-                .clone()
-                .unwrap()
-                .upgrade()
-                .unwrap()
-                .deref()
-                .borrow()
-                .key,
-            3
+            tree.validate(),
+            Err(InvariantViolation::Length {
+                recorded: 5,
+                counted: 4
+            })
         );
-        assert_eq!(get_left_parent(&tree.root), 2);
+    }
 
-        //    2             3
-        //   3            1   2
-        //  1 4     ->   0   4
-        // 0
-        tree.rotate(tree.root.clone().unwrap(), true);
-        assert_eq!(tree.root.clone().unwrap().as_ptr(), nodes[3].as_ptr());
-        assert!(tree.root.unwrap().deref().borrow().parent.is_none());
+    #[test]
+    fn reuse_slots() {
+        let mut tree = RedBlackTree::default();
+        for i in 0..100 {
+            tree.add(i, i);
+        }
+        for i in (0..100).step_by(2) {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        for i in 100..150 {
+            tree.add(i, i);
+        }
+        assert!(tree.is_valid());
+        assert_eq!(tree.store.next.load(atomic::Ordering::Relaxed), 100);
+        assert!(tree.keys().eq((1..100).step_by(2).chain(100..150)));
     }
 
     #[test]
-    fn rc_eq() {
-        let a = Rc::new(RefCell::new(1));
-        let b = Rc::new(RefCell::new(1));
-        assert!(!a.as_ptr().eq(&b.as_ptr()));
-        assert_eq!(a, b);
-        assert_ne!(a.as_ptr(), b.as_ptr());
+    fn shared_store() {
+        let mut tree = RedBlackTree::default();
+        for i in 0..1000 {
+            tree.add(i, i);
+        }
+        let mut shards = vec![];
+        for key in (100..1000).rev().step_by(100) {
+            shards.push(tree.split_off(&(key - 99)));
+        }
+        // 切分与连接只重新链接节点，不会分配新的槽位
+        for shard in shards.iter_mut().rev() {
+            assert!(Arc::ptr_eq(&tree.store, &shard.store));
+            assert_eq!(shard.length(), 100);
+            assert!(shard.is_valid());
+            tree.append(shard);
+            assert!(tree.is_valid());
+        }
+        assert_eq!(tree.length(), 1000);
+        assert!(tree.keys().eq(0..1000));
+        let right = tree.split_off(&500);
+        let (key, value) = tree
+            .last_key_value()
+            .map(|pair| (*pair.key(), *pair.value()))
+            .unwrap();
+        tree.remove(&key);
+        let tree = RedBlackTree::join(tree, key, value, right);
+        assert!(tree.is_valid());
+        assert!(tree.keys().eq(0..1000));
+        assert_eq!(tree.store.next.load(atomic::Ordering::Relaxed), 1000);
+
+        // 删除共用存储的树后，它的槽位由其他树复用
+        let mut other = RedBlackTree::new_in(&tree);
+        for i in 1000..1100 {
+            other.add(i, i);
+        }
+        drop(other);
+        let mut tree = tree;
+        for i in 1000..1100 {
+            tree.add(i, i);
+        }
+        assert!(tree.is_valid());
+        assert_eq!(tree.store.next.load(atomic::Ordering::Relaxed), 1100);
+        assert!(tree.into_iter().map(|(key, _)| key).eq(0..1100));
+    }
+
+    #[test]
+    fn rotate() {
+        //    1            0
+        //  0   2   ->       1
+        //                     2
+        let mut tree = RedBlackTree::default();
+        for i in 0..3 {
+            tree.add(i, ());
+        }
+        assert_eq!(tree.node(tree.root).key, 1);
+        tree.rotate(tree.root, true);
+        let root = tree.node(tree.root);
+        assert_eq!((root.key, root.parent, root.left), (0, NIL, NIL));
+        let right = tree.node(root.right);
+        assert_eq!(right.key, 1);
+        assert_eq!(right.parent, tree.root);
+        assert_eq!(tree.node(right.right).key, 2);
+        assert_eq!(tree.node(right.right).parent, root.right);
+
+        tree.rotate(tree.root, false);
+        let root = tree.node(tree.root);
+        assert_eq!(root.key, 1);
+        assert_eq!(tree.node(root.left).key, 0);
+        assert_eq!(tree.node(root.left).parent, tree.root);
+        assert_eq!(tree.node(root.right).key, 2);
     }
 }
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use crate::red_black::RedBlackTree;
+
+// 以红黑树为基础的多重映射，同一个键下的值按添加顺序保存
+pub struct RedBlackMultiMap<K: Ord, V> {
+    tree: RedBlackTree<K, VecDeque<V>>,
+    length: usize,
+}
+
+impl<K: Ord, V> Default for RedBlackMultiMap<K, V> {
+    fn default() -> Self {
+        Self {
+            tree: RedBlackTree::default(),
+            length: 0,
+        }
+    }
+}
+
+impl<K: Ord, V> RedBlackMultiMap<K, V> {
+    // 值的总数，重复的键分别计数
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    // 不同键的个数
+    pub fn key_count(&self) -> usize {
+        self.tree.length()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.tree.is_valid() && self.tree.iter().all(|pair| !pair.value().is_empty())
+    }
+
+    pub fn add(&mut self, key: K, value: V) {
+        self.tree.entry(key).or_default().push_back(value);
+        self.length += 1;
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.tree.contains_key(key)
+    }
+
+    pub fn count(&self, key: &K) -> usize {
+        self.tree.get(key).map_or(0, |pair| pair.value().len())
+    }
+
+    // 按添加顺序返回 key 下的所有值
+    pub fn get_all(&self, key: &K) -> impl Iterator<Item = &V> {
+        self.tree
+            .get(key)
+            .into_iter()
+            .flat_map(|pair| pair.value().iter())
+    }
+
+    // 删除 key 下最早添加的值
+    pub fn remove_one(&mut self, key: &K) -> Option<V> {
+        let mut pair = self.tree.get_mut(key)?;
+        let value = pair.value_mut().pop_front();
+        if pair.value().is_empty() {
+            self.tree.remove(key);
+        }
+        self.length -= 1;
+        value
+    }
+
+    // 删除 key 下的所有值，按添加顺序返回
+    pub fn remove_all(&mut self, key: &K) -> Vec<V> {
+        let values = Vec::from(self.tree.remove(key).unwrap_or_default());
+        self.length -= values.len();
+        values
+    }
+
+    // 按键的顺序遍历，同一个键的值按添加顺序排列
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.tree.iter().flat_map(|pair| {
+            let key = pair.key();
+            pair.value().iter().map(move |value| (key, value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rand::Rng;
+
+    use crate::red_black_multi_map::*;
+
+    #[test]
+    fn duplicate_keys() {
+        let mut map = RedBlackMultiMap::default();
+        for (timestamp, event) in [(3, "c"), (1, "a"), (3, "d"), (2, "b"), (3, "e")] {
+            map.add(timestamp, event);
+        }
+        assert_eq!(map.length(), 5);
+        assert_eq!(map.key_count(), 3);
+        assert_eq!(map.count(&3), 3);
+        assert_eq!(map.count(&4), 0);
+        assert_eq!(
+            map.get_all(&3).copied().collect::<Vec<_>>(),
+            vec!["c", "d", "e"]
+        );
+        assert_eq!(map.get_all(&4).count(), 0);
+        assert_eq!(
+            map.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(),
+            vec![(1, "a"), (2, "b"), (3, "c"), (3, "d"), (3, "e")]
+        );
+
+        assert_eq!(map.remove_one(&3), Some("c"));
+        assert_eq!(map.remove_one(&1), Some("a"));
+        assert_eq!(map.remove_one(&1), None);
+        assert!(!map.contains_key(&1));
+        assert_eq!(map.remove_all(&3), vec!["d", "e"]);
+        assert_eq!(map.remove_all(&3), Vec::<&str>::new());
+        assert_eq!(map.length(), 1);
+        assert!(map.is_valid());
+    }
+
+    #[test]
+    fn random() {
+        let mut rng = rand::thread_rng();
+        let mut map = RedBlackMultiMap::default();
+        let mut std_map: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for i in 0..5000 {
+            let key = rng.gen_range(0..100);
+            match rng.gen_range(0..10) {
+                0 => {
+                    let expected = std_map.remove(&key).unwrap_or_default();
+                    assert_eq!(map.remove_all(&key), expected);
+                }
+                1..=3 => {
+                    let expected = std_map.get_mut(&key).map(|values| values.remove(0));
+                    if std_map.get(&key).is_some_and(|values| values.is_empty()) {
+                        std_map.remove(&key);
+                    }
+                    assert_eq!(map.remove_one(&key), expected);
+                }
+                _ => {
+                    map.add(key, i);
+                    std_map.entry(key).or_default().push(i);
+                }
+            }
+            assert_eq!(map.count(&key), std_map.get(&key).map_or(0, Vec::len));
+        }
+        assert!(map.is_valid());
+        assert_eq!(map.key_count(), std_map.len());
+        assert_eq!(map.length(), std_map.values().map(Vec::len).sum::<usize>());
+        assert!(map.iter().eq(std_map
+            .iter()
+            .flat_map(|(key, values)| values.iter().map(move |value| (key, value)))));
+    }
+}
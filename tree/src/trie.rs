@@ -30,9 +30,9 @@ impl<T> Trie<T> {
     pub fn add(&mut self, path: &str, value: T) -> Option<T> {
         let mut chars = path.chars();
         if let Some(c) = chars.next() {
-            let mut node = self.root.entry(c).or_insert_with(Node::default);
+            let mut node = self.root.entry(c).or_default();
             for c in chars {
-                node = node.next.entry(c).or_insert_with(Node::default);
+                node = node.next.entry(c).or_default();
             }
             let mut value = Some(value);
             std::mem::swap(&mut value, &mut node.value);
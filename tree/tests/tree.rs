@@ -1,3 +1,7 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+
 use tree::binary::*;
 
 struct IoTDevice {
@@ -5,6 +9,15 @@ struct IoTDevice {
     _address: String,
 }
 
+impl IoTDevice {
+    fn new(id: u64, address: &str) -> Self {
+        Self {
+            id,
+            _address: address.to_string(),
+        }
+    }
+}
+
 #[test]
 fn find() {}
 
@@ -79,3 +92,263 @@ fn walk() {
     set.walk(|(_item, dev)| items.push(dev.id));
     assert_eq!(items, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
 }
+
+#[test]
+fn remove() {
+    let mut rng = rand::thread_rng();
+    let mut tree = BinarySearchTree::default();
+    let mut std_tree = BTreeMap::new();
+    for _ in 0..10_000 {
+        let key = rng.gen_range(0..500);
+        if rng.gen_bool(0.5) {
+            assert_eq!(tree.insert(key, key * 10), std_tree.insert(key, key * 10));
+        } else {
+            assert_eq!(tree.remove(&key), std_tree.remove(&key));
+        }
+        assert_eq!(tree.find(&key), std_tree.get(&key));
+        assert_eq!(tree.len(), std_tree.len());
+    }
+
+    let mut items = Vec::default();
+    tree.walk(|(&key, &value)| items.push((key, value)));
+    assert_eq!(items, std_tree.into_iter().collect::<Vec<(i32, i32)>>());
+}
+
+#[test]
+fn registry() {
+    let mut registry = BinarySearchTree::default();
+    assert!(registry.is_empty());
+    assert!(registry.insert(2, IoTDevice::new(2, "10.0.0.2")).is_none());
+    assert!(registry.insert(1, IoTDevice::new(1, "10.0.0.1")).is_none());
+    assert_eq!(registry.len(), 2);
+
+    let old = registry.insert(2, IoTDevice::new(2, "10.0.0.20")).unwrap();
+    assert_eq!(old._address, "10.0.0.2");
+    assert_eq!(registry.len(), 2);
+    assert!(registry.contains_key(&1));
+    assert!(!registry.contains_key(&3));
+
+    registry.get_mut(&1).unwrap()._address = "10.0.0.10".to_string();
+    assert_eq!(registry.find(&1).unwrap()._address, "10.0.0.10");
+    assert!(registry.get_mut(&3).is_none());
+
+    registry
+        .entry(3)
+        .or_insert_with(|| IoTDevice::new(3, "10.0.0.3"));
+    registry
+        .entry(3)
+        .and_modify(|dev| dev._address = "10.0.0.30".to_string())
+        .or_insert_with(|| unreachable!());
+    assert_eq!(registry.find(&3).unwrap()._address, "10.0.0.30");
+    assert_eq!(registry.len(), 3);
+
+    match registry.entry(2) {
+        Entry::Occupied(entry) => {
+            let (id, dev) = entry.remove_entry();
+            assert_eq!(id, dev.id);
+        }
+        Entry::Vacant(_) => unreachable!(),
+    }
+    assert_eq!(registry.len(), 2);
+
+    let mut ids = Vec::default();
+    registry.walk(|(&id, _dev)| ids.push(id));
+    assert_eq!(ids, vec![1, 3]);
+}
+
+#[test]
+fn entry_count() {
+    let mut counts = BinarySearchTree::default();
+    for word in "a b a c b a".split(' ') {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+    let mut items = Vec::default();
+    counts.walk(|(&word, &count)| items.push((word, count)));
+    assert_eq!(items, vec![("a", 3), ("b", 2), ("c", 1)]);
+}
+
+fn traversal_tree() -> BinarySearchTree<i32, i32> {
+    //       4
+    //    2     7
+    //   1 3   6 8
+    //        5   9
+    let mut tree = BinarySearchTree::default();
+    for &key in &[4, 7, 8, 2, 1, 3, 6, 9, 5] {
+        tree.insert(key, key * 10);
+    }
+    tree
+}
+
+#[test]
+fn iter() {
+    let tree = traversal_tree();
+    assert_eq!(
+        tree.iter().map(|(&k, _)| k).collect::<Vec<i32>>(),
+        (1..=9).collect::<Vec<i32>>()
+    );
+    assert_eq!(
+        tree.iter().rev().map(|(&k, _)| k).collect::<Vec<i32>>(),
+        (1..=9).rev().collect::<Vec<i32>>()
+    );
+    assert_eq!(tree.iter().size_hint(), (9, Some(9)));
+
+    let mut iter = tree.iter();
+    assert_eq!(iter.next(), Some((&1, &10)));
+    assert_eq!(iter.next_back(), Some((&9, &90)));
+    assert_eq!(iter.next_back(), Some((&8, &80)));
+    assert_eq!(iter.next(), Some((&2, &20)));
+    assert_eq!(
+        iter.map(|(&k, _)| k).collect::<Vec<i32>>(),
+        vec![3, 4, 5, 6, 7]
+    );
+
+    assert_eq!(tree.iter().find(|(&k, _)| k > 5), Some((&6, &60)));
+    assert_eq!(BinarySearchTree::<i32, i32>::default().iter().next(), None);
+}
+
+#[test]
+fn iter_mut_and_into_iter() {
+    let mut tree = traversal_tree();
+    for (&key, value) in tree.iter_mut() {
+        *value += key;
+    }
+    for (_key, value) in &mut tree {
+        *value += 1;
+    }
+    assert_eq!(
+        (&tree)
+            .into_iter()
+            .map(|(&k, &v)| (k, v))
+            .collect::<Vec<_>>(),
+        (1..=9).map(|k| (k, k * 11 + 1)).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        tree.into_iter().collect::<Vec<_>>(),
+        (1..=9).map(|k| (k, k * 11 + 1)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn traversal_orders() {
+    let tree = traversal_tree();
+    let keys = |iter: &mut dyn Iterator<Item = (&i32, &i32)>| iter.map(|(&k, _)| k).collect();
+    let preorder: Vec<i32> = keys(&mut tree.preorder());
+    assert_eq!(preorder, vec![4, 2, 1, 3, 7, 6, 5, 8, 9]);
+    let postorder: Vec<i32> = keys(&mut tree.postorder());
+    assert_eq!(postorder, vec![1, 3, 2, 5, 6, 9, 8, 7, 4]);
+    let level_order: Vec<i32> = keys(&mut tree.level_order());
+    assert_eq!(level_order, vec![4, 2, 7, 1, 3, 6, 8, 5, 9]);
+}
+
+#[test]
+fn sorted_insert() {
+    const NUM: u32 = 5_000;
+    let mut tree = BinarySearchTree::default();
+    for key in 0..NUM {
+        assert_eq!(tree.insert(key, key), None);
+    }
+    assert_eq!(tree.len(), NUM as usize);
+    assert_eq!(tree.find(&(NUM - 1)), Some(&(NUM - 1)));
+    assert!(tree.iter().map(|(&k, _)| k).eq(0..NUM));
+    for key in (0..NUM).rev() {
+        assert_eq!(tree.remove(&key), Some(key));
+    }
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn order_statistics() {
+    let mut rng = rand::thread_rng();
+    let mut tree = BinarySearchTree::default();
+    let mut std_tree = BTreeMap::new();
+    for _ in 0..5_000 {
+        let key = rng.gen_range(0..300);
+        match rng.gen_range(0..3) {
+            0 => assert_eq!(tree.insert(key, key), std_tree.insert(key, key)),
+            1 => assert_eq!(tree.remove(&key), std_tree.remove(&key)),
+            _ => {
+                *tree.entry(key).or_insert(0) += 1;
+                *std_tree.entry(key).or_insert(0) += 1;
+            }
+        }
+
+        let k = rng.gen_range(0..=std_tree.len());
+        assert_eq!(tree.select(k), std_tree.iter().nth(k));
+        let target = rng.gen_range(0..300);
+        assert_eq!(tree.rank(&target), std_tree.range(..target).count());
+    }
+}
+
+#[test]
+fn navigation() {
+    let mut rng = rand::thread_rng();
+    let mut tree = BinarySearchTree::default();
+    let mut std_tree = BTreeMap::new();
+    assert_eq!(tree.first(), None);
+    assert_eq!(tree.last(), None);
+    assert_eq!(tree.floor(&0), None);
+    assert_eq!(tree.range(..).next(), None);
+    for _ in 0..500 {
+        let key = rng.gen_range(0..1000) * 2;
+        tree.insert(key, key);
+        std_tree.insert(key, key);
+    }
+
+    assert_eq!(tree.first(), std_tree.iter().next());
+    assert_eq!(tree.last(), std_tree.iter().next_back());
+    for target in -1..2001 {
+        assert_eq!(tree.floor(&target), std_tree.range(..=target).next_back());
+        assert_eq!(tree.ceiling(&target), std_tree.range(target..).next());
+        assert_eq!(
+            tree.predecessor(&target),
+            std_tree.range(..target).next_back()
+        );
+        assert_eq!(tree.successor(&target), std_tree.range(target + 1..).next());
+    }
+
+    for _ in 0..200 {
+        let start = rng.gen_range(-10..2010);
+        let end = rng.gen_range(start..2020);
+        assert!(tree.range(start..end).eq(std_tree.range(start..end)));
+        assert!(tree.range(start..=end).eq(std_tree.range(start..=end)));
+        assert!(tree.range(..end).eq(std_tree.range(..end)));
+        assert!(tree.range(start..).eq(std_tree.range(start..)));
+    }
+    assert!(tree.range(..).eq(std_tree.iter()));
+}
+
+#[test]
+fn from_sorted_and_rebalance() {
+    let tree = BinarySearchTree::from_sorted((0..1000).map(|i| (i, i * 10)));
+    assert_eq!(tree.len(), 1000);
+    assert!(tree
+        .iter()
+        .map(|(&k, &v)| (k, v))
+        .eq((0..1000).map(|i| (i, i * 10))));
+    assert_eq!(tree.select(500), Some((&500, &5000)));
+    assert_eq!(tree.rank(&500), 500);
+
+    let mut rng = rand::thread_rng();
+    let mut tree = BinarySearchTree::default();
+    let mut std_tree = BTreeMap::new();
+    for _ in 0..2000 {
+        let key = rng.gen_range(0..1000);
+        tree.insert(key, key);
+        std_tree.insert(key, key);
+    }
+    tree.rebalance();
+    assert_eq!(tree.len(), std_tree.len());
+    assert!(tree.iter().eq(std_tree.iter()));
+    for _ in 0..1000 {
+        let key = rng.gen_range(0..1000);
+        assert_eq!(tree.remove(&key), std_tree.remove(&key));
+        let k = rng.gen_range(0..=std_tree.len());
+        assert_eq!(tree.select(k), std_tree.iter().nth(k));
+    }
+}
+
+#[test]
+#[should_panic]
+fn from_unsorted() {
+    BinarySearchTree::from_sorted(vec![(2, ()), (1, ())]);
+}